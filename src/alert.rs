@@ -0,0 +1,56 @@
+use model::{Contribution, Deviation, Prediction};
+use reddit::RawPostFeatures;
+
+/// A post the model flagged as belonging to another subreddit
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub id: String,
+    pub title: String,
+    pub author: String,
+    pub permalink: String,
+    pub created_utc: f64,
+    /// The subreddit the post was predicted to belong to
+    pub label: String,
+    pub probability: f32,
+    /// The features that contributed most to the prediction, see
+    /// `ModelBundle::feature_contributions`
+    pub contributions: Vec<Contribution>,
+    /// The features that set the post furthest apart from the training posts. They are not
+    /// necessarily the ones the prediction was based on.
+    pub unusual_features: Vec<Deviation>,
+}
+
+impl Alert {
+    pub fn new(post: &RawPostFeatures, prediction: &Prediction) -> Alert {
+        Alert {
+            id: post.id.clone(),
+            title: post.title.clone(),
+            author: post.author.clone(),
+            permalink: post.permalink.clone(),
+            created_utc: post.created_utc,
+            label: prediction.label.clone(),
+            probability: prediction.probability,
+            contributions: vec![],
+            unusual_features: vec![],
+        }
+    }
+
+    pub fn with_contributions(mut self, contributions: Vec<Contribution>) -> Alert {
+        self.contributions = contributions;
+        self
+    }
+
+    pub fn with_unusual_features(mut self, features: Vec<Deviation>) -> Alert {
+        self.unusual_features = features;
+        self
+    }
+
+    /// The absolute url of the post
+    pub fn url(&self) -> String {
+        if self.permalink.starts_with('/') {
+            format!("https://www.reddit.com{}", self.permalink)
+        } else {
+            self.permalink.clone()
+        }
+    }
+}
@@ -0,0 +1,83 @@
+extern crate clap;
+extern crate playrust_alert;
+
+use clap::{Arg, App, SubCommand};
+use playrust_alert::dataset::Dataset;
+use playrust_alert::store::PostStore;
+use playrust_alert::Result;
+
+use std::io::{self, Write};
+use std::process;
+
+fn run() -> Result<()> {
+    let matches = App::new("Dataset")
+                      .version("1.0")
+                      .about("Manages the SQLite dataset that posts are trained on")
+                      .arg(Arg::with_name("dataset")
+                               .help("The SQLite dataset, created if it does not exist")
+                               .required(true)
+                               .index(1))
+                      .subcommand(SubCommand::with_name("import")
+                                      .about("Imports the posts of a CSV")
+                                      .arg(Arg::with_name("csv").required(true).index(1)))
+                      .subcommand(SubCommand::with_name("import-store")
+                                      .about("Imports the posts of a scraper post store")
+                                      .arg(Arg::with_name("store").required(true).index(1)))
+                      .subcommand(SubCommand::with_name("export")
+                                      .about("Writes the posts to a CSV")
+                                      .arg(Arg::with_name("csv").required(true).index(1))
+                                      .arg(Arg::with_name("snapshot")
+                                               .long("snapshot")
+                                               .takes_value(true)
+                                               .help("Only export the posts of this snapshot")))
+                      .subcommand(SubCommand::with_name("tag")
+                                      .about("Tags every post currently in the dataset")
+                                      .arg(Arg::with_name("tag").required(true).index(1)))
+                      .subcommand(SubCommand::with_name("label")
+                                      .about("Labels a post, overriding its subreddit")
+                                      .arg(Arg::with_name("post_id").required(true).index(1))
+                                      .arg(Arg::with_name("label").required(true).index(2))
+                                      .arg(Arg::with_name("source")
+                                               .long("source")
+                                               .takes_value(true)
+                                               .help("Where the label came from. Defaults to \
+                                                      \"manual\"")))
+                      .get_matches();
+
+    let mut dataset = try!(Dataset::open(matches.value_of("dataset").unwrap()));
+
+    match matches.subcommand() {
+        ("import", Some(m)) => {
+            let inserted = try!(dataset.import_csv(m.value_of("csv").unwrap()));
+            println!("imported {} new posts", inserted);
+        }
+        ("import-store", Some(m)) => {
+            let store = try!(PostStore::open(m.value_of("store").unwrap()));
+            let inserted = try!(dataset.import_store(&store));
+            println!("imported {} new posts", inserted);
+        }
+        ("export", Some(m)) => {
+            let written = try!(dataset.export_csv(m.value_of("csv").unwrap(),
+                                                  m.value_of("snapshot")));
+            println!("exported {} posts", written);
+        }
+        ("tag", Some(m)) => {
+            let tagged = try!(dataset.tag_snapshot(m.value_of("tag").unwrap()));
+            println!("tagged {} posts", tagged);
+        }
+        ("label", Some(m)) => {
+            try!(dataset.label(m.value_of("post_id").unwrap(),
+                               m.value_of("label").unwrap(),
+                               m.value_of("source").unwrap_or("manual")));
+        }
+        _ => println!("{}", matches.usage()),
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        let _ = writeln!(io::stderr(), "error: {}", e);
+        process::exit(1);
+    }
+}
@@ -4,10 +4,30 @@ extern crate playrust_alert;
 extern crate tiny_keccak;
 
 use clap::{Arg, App};
+use playrust_alert::dataset::Dataset;
 use playrust_alert::reddit::RedditClient;
-use playrust_alert::reddit::get_posts;
+use playrust_alert::reddit::parse_post;
+use playrust_alert::oauth::{Credentials, OAuth};
+use playrust_alert::ratelimit::RateLimit;
+use playrust_alert::scraper::scrape;
+use playrust_alert::store::PostStore;
+use playrust_alert::util::parse_positive;
+use playrust_alert::Result;
 
-fn get_args() -> String {
+use std::io::{self, Write};
+use std::process;
+use std::time::Duration;
+
+struct Args {
+    subreddit: String,
+    user_agent: Option<String>,
+    requests_per_minute: Option<usize>,
+    credentials: Option<String>,
+    store: Option<String>,
+    dataset: Option<String>,
+}
+
+fn get_args() -> Result<Args> {
     let matches = App::new("Reddit Feature Generator")
                       .version("1.0")
                       .about("Collects posts from a subreddit")
@@ -15,29 +35,107 @@ fn get_args() -> String {
                                .help("The subreddit to scrape")
                                .required(true)
                                .index(1))
+                      .arg(Arg::with_name("user-agent")
+                               .long("user-agent")
+                               .takes_value(true)
+                               .help("The User-Agent to identify as to reddit"))
+                      .arg(Arg::with_name("requests-per-minute")
+                               .long("requests-per-minute")
+                               .takes_value(true)
+                               .help("The maximum number of requests to send per minute"))
+                      .arg(Arg::with_name("credentials")
+                               .long("credentials")
+                               .takes_value(true)
+                               .help("A JSON file with OAuth credentials. Defaults to the \
+                                      REDDIT_CLIENT_ID, REDDIT_CLIENT_SECRET, REDDIT_USERNAME \
+                                      and REDDIT_PASSWORD environment variables"))
+                      .arg(Arg::with_name("store")
+                               .long("store")
+                               .takes_value(true)
+                               .help("Scrape incrementally into the post store in this \
+                                      directory, resuming the previous scrape, then write every \
+                                      stored post to the CSV"))
+                      .arg(Arg::with_name("dataset")
+                               .long("dataset")
+                               .takes_value(true)
+                               .requires("store")
+                               .help("Import the stored posts into this SQLite dataset instead \
+                                      of writing them to a CSV"))
                       .get_matches();
 
-    matches.value_of("subreddit").unwrap().to_owned()
+    let requests_per_minute = match matches.value_of("requests-per-minute") {
+        Some(r) => Some(try!(parse_positive("requests-per-minute", r))),
+        None => None,
+    };
+
+    Ok(Args {
+        subreddit: matches.value_of("subreddit").unwrap().to_owned(),
+        user_agent: matches.value_of("user-agent").map(String::from),
+        requests_per_minute: requests_per_minute,
+        credentials: matches.value_of("credentials").map(String::from),
+        store: matches.value_of("store").map(String::from),
+        dataset: matches.value_of("dataset").map(String::from),
+    })
 }
 
 
+fn run() -> Result<()> {
+    let args = try!(get_args());
+    let sub = args.subreddit;
 
-fn main() {
     let mut client = RedditClient::new();
-    let sub = get_args();
+    if let Some(ref user_agent) = args.user_agent {
+        client = client.user_agent(user_agent);
+    }
+    if let Some(requests_per_minute) = args.requests_per_minute {
+        client = client.rate_limit(RateLimit {
+            requests_per_window: requests_per_minute,
+            window: Duration::from_secs(60),
+            ..RateLimit::default()
+        });
+    }
+    let credentials = args.credentials.as_ref().map(|c| c.as_str());
+    if let Some(credentials) = try!(Credentials::load(credentials)) {
+        client = client.oauth(OAuth::new(credentials));
+    }
 
-    // let mut raw_posts = Vec::with_capacity(1000);
+    if let Some(ref store) = args.store {
+        let mut store = try!(PostStore::open(store));
+        let stats = try!(scrape(&mut client, &mut store, &sub, 100));
+        println!("fetched {} pages, stored {} new posts, skipped {} malformed posts",
+                 stats.pages,
+                 stats.inserted,
+                 stats.malformed);
+
+        if let Some(ref dataset) = args.dataset {
+            let inserted = try!(try!(Dataset::open(dataset)).import_store(&store));
+            println!("imported {} new posts into {}", inserted, dataset);
+            return Ok(());
+        }
 
-    let mut wtr = csv::Writer::from_file(format!("./{}.csv", sub)).unwrap();
+        let mut wtr = try!(csv::Writer::from_file(format!("./{}.csv", sub)));
+        for record in store.posts() {
+            try!(wtr.encode(record));
+        }
+        return Ok(());
+    }
+
+    let mut wtr = try!(csv::Writer::from_file(format!("./{}.csv", sub)));
     let mut after = None;
 
     loop {
         println!("fetching");
-        let (features, new_after) = client.get_raw_features(&sub, 100, &after);
+        let (features, new_after) = try!(client.get_raw_features(&sub, 100, &after));
         after = new_after;
-        let posts = get_posts(features);
-        for record in posts.into_iter() {
-            let _ = wtr.encode(record);
+
+        // A single malformed post should not abort the whole scrape
+        for feature in features {
+            match parse_post(&feature) {
+                Ok(record) => try!(wtr.encode(record)),
+                Err(e) => {
+                    let _ = writeln!(io::stderr(), "skipping malformed post: {}", e);
+                }
+            }
         }
 
         if after.is_none() {
@@ -45,7 +143,12 @@ fn main() {
         }
     }
 
+    Ok(())
+}
 
-
-
+fn main() {
+    if let Err(e) = run() {
+        let _ = writeln!(io::stderr(), "error: {}", e);
+        process::exit(1);
+    }
 }
@@ -9,43 +9,72 @@ extern crate ndarray;
 
 extern crate clap;
 extern crate csv;
-extern crate dedup_by;
 extern crate rand;
 extern crate rayon;
 extern crate rsml;
 extern crate rustc_serialize;
 extern crate serde_json;
 extern crate stopwatch;
-extern crate tfidf;
 
 use clap::{Arg, App};
-use dedup_by::dedup_by;
+use playrust_alert::dataset::Dataset;
+use playrust_alert::dedup::{DedupConfig, dedup_posts, find_duplicates};
 use playrust_alert::reddit::RawPostFeatures;
+use playrust_alert::util::{parse_fraction, read_posts};
+use playrust_alert::Result;
 use rsml::tfidf_helper::get_unique_word_list;
 
 use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::process;
 
-fn get_train_data() -> Vec<RawPostFeatures> {
+fn get_train_data() -> Result<Vec<RawPostFeatures>> {
     let matches = App::new("Model Generator")
                       .version("1.0")
                       .about("Generates a random forest based on a training set")
                       .arg(Arg::with_name("train")
                                .help("The CSV to train on")
-                               .required(true)
+                               .required_unless("dataset")
                                .index(1))
+                      .arg(Arg::with_name("dataset")
+                               .long("dataset")
+                               .takes_value(true)
+                               .conflicts_with("train")
+                               .help("Count the words of the labeled posts in this SQLite dataset \
+                                      instead"))
+                      .arg(Arg::with_name("snapshot")
+                               .long("snapshot")
+                               .takes_value(true)
+                               .requires("dataset")
+                               .help("Only use the posts tagged with this dataset snapshot"))
+                      .arg(Arg::with_name("duplicate-threshold")
+                               .long("duplicate-threshold")
+                               .takes_value(true)
+                               .help("The Jaccard similarity above which posts are considered \
+                                      near-duplicates. Defaults to 0.8"))
+                      .arg(Arg::with_name("duplicate-report")
+                               .long("duplicate-report")
+                               .takes_value(true)
+                               .help("Write the clusters of near-duplicates found to this file"))
                       .get_matches();
 
-    let train_path = matches.value_of("train").unwrap();
+    let threshold = match matches.value_of("duplicate-threshold") {
+        Some(t) => try!(parse_fraction("duplicate-threshold", t)),
+        None => DedupConfig::default().threshold,
+    };
 
-    let mut rdr = csv::Reader::from_file(train_path).unwrap();
+    let posts: Vec<RawPostFeatures> = match matches.value_of("dataset") {
+        Some(path) => try!(try!(Dataset::open(path)).labeled_posts(matches.value_of("snapshot"))),
+        None => try!(read_posts(matches.value_of("train").unwrap())),
+    };
 
-    let mut posts: Vec<RawPostFeatures> = rdr.decode()
-                                             .map(|raw_post| raw_post.unwrap())
-                                             .collect();
-
-    posts.sort_by(|a, b| a.title.cmp(&b.title));
-    dedup_by(&mut posts, |a, b| a.title == b.title);
-    posts
+    let config = DedupConfig { threshold: threshold, ..DedupConfig::default() };
+    let clusters = try!(find_duplicates(&posts, &config));
+    if let Some(path) = matches.value_of("duplicate-report") {
+        try!(clusters.write_report(&posts, try!(File::create(path))));
+    }
+    Ok(dedup_posts(posts, &clusters))
 }
 
 
@@ -62,8 +91,8 @@ fn word_freqs(posts: &[RawPostFeatures]) -> BTreeMap<String, u64> {
     map
 }
 
-fn main() {
-    let posts = get_train_data();
+fn run() -> Result<()> {
+    let posts = try!(get_train_data());
     let (rust, play): (Vec<RawPostFeatures>, Vec<RawPostFeatures>) = posts.into_iter()
                                                                           .partition(|post| {
                                                                               post.subreddit ==
@@ -77,5 +106,12 @@ fn main() {
     // play_word_freq.sort_by(|a, b| a.1.cmp(&b.1));
 
     println!("{:#?}", rust_word_freq);
+    Ok(())
+}
 
+fn main() {
+    if let Err(e) = run() {
+        let _ = writeln!(io::stderr(), "error: {}", e);
+        process::exit(1);
+    }
 }
@@ -0,0 +1,90 @@
+extern crate clap;
+extern crate hyper;
+extern crate playrust_alert;
+
+use clap::{Arg, App};
+use hyper::header::ContentType;
+use hyper::server::{Handler, Request, Response, Server};
+use hyper::status::StatusCode;
+use hyper::uri::RequestUri;
+
+use playrust_alert::transport::{self, FixtureTransport, Transport};
+use playrust_alert::{Error, Result};
+
+use std::io::{self, Read, Write};
+use std::process;
+use std::sync::Mutex;
+
+/// Answers reddit API requests from a directory of fixtures, so the watcher and the service can
+/// be run end to end without reaching reddit
+struct FixtureHandler {
+    fixtures: Mutex<FixtureTransport>,
+}
+
+impl Handler for FixtureHandler {
+    fn handle<'a, 'k>(&'a self, mut req: Request<'a, 'k>, mut res: Response<'a>) {
+        let path = match req.uri {
+            RequestUri::AbsolutePath(ref path) => path.clone(),
+            _ => String::new(),
+        };
+        let request = match req.method {
+            hyper::method::Method::Post => {
+                let mut body = String::new();
+                let _ = req.read_to_string(&mut body);
+                transport::Request::post(&path, body)
+            }
+            _ => transport::Request::get(&path),
+        };
+
+        let response = {
+            let mut fixtures = self.fixtures.lock().unwrap_or_else(|e| e.into_inner());
+            fixtures.send(&request)
+        };
+
+        match response {
+            Ok(response) => {
+                let _ = writeln!(io::stderr(), "{:?} {} {}", request.method, path, response.status);
+                *res.status_mut() = StatusCode::from_u16(response.status);
+                res.headers_mut().set(ContentType::json());
+                let _ = res.send(response.body.as_bytes());
+            }
+            Err(e) => {
+                let _ = writeln!(io::stderr(), "{:?} {} failed: {}", request.method, path, e);
+                *res.status_mut() = StatusCode::InternalServerError;
+            }
+        }
+    }
+}
+
+fn run() -> Result<()> {
+    let matches = App::new("Mock Reddit")
+                      .version("1.0")
+                      .about("Serves recorded reddit responses")
+                      .arg(Arg::with_name("addr")
+                               .long("addr")
+                               .takes_value(true)
+                               .help("The address to listen on. Defaults to 127.0.0.1:3001"))
+                      .arg(Arg::with_name("fixtures")
+                               .long("fixtures")
+                               .takes_value(true)
+                               .help("The directory of fixtures to serve. Defaults to \
+                                      ./fixtures/reddit"))
+                      .get_matches();
+
+    let addr = matches.value_of("addr").unwrap_or("127.0.0.1:3001");
+    let dir = matches.value_of("fixtures").unwrap_or("./fixtures/reddit");
+
+    let handler = FixtureHandler { fixtures: Mutex::new(FixtureTransport::from_dir(dir)) };
+    let server = try!(Server::http(addr).map_err(Error::Http));
+
+    println!("serving {} on {}", dir, addr);
+    try!(server.handle(handler).map_err(Error::Http));
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        let _ = writeln!(io::stderr(), "error: {}", e);
+        process::exit(1);
+    }
+}
@@ -7,7 +7,6 @@ extern crate playrust_alert;
 
 extern crate clap;
 extern crate csv;
-extern crate dedup_by;
 extern crate rand;
 extern crate rayon;
 extern crate rustlearn;
@@ -17,197 +16,236 @@ extern crate stopwatch;
 
 use clap::{Arg, App};
 
-use rustlearn::cross_validation::cross_validation::CrossValidation;
-
-use dedup_by::dedup_by;
-
-use playrust_alert::reddit::{RawPostFeatures, ProcessedPostFeatures};
-use playrust_alert::feature_extraction::{convert_author_to_popularity, convert_is_self,
-                                         check_for_code, subs_to_float, interesting_word_freq,
-                                         symbol_counts};
+use playrust_alert::cross_validation::{SplitStrategy, author_groups, cross_validate, split};
+use playrust_alert::dataset::Dataset;
+use playrust_alert::dedup::{DedupConfig, DuplicateClusters, dedup_posts, find_duplicates};
+use playrust_alert::evaluation::{EvaluationReport, tune_thresholds};
+use playrust_alert::lexicon::load_lexicons;
+use playrust_alert::reddit::RawPostFeatures;
+use playrust_alert::model::{Forest, ModelBundle, TrainingMetadata};
+use playrust_alert::pipeline::FeaturePipeline;
+use playrust_alert::vectorizer::{Norm, TfScheme};
 
 use playrust_alert::util::*;
+use playrust_alert::{Error, Result};
 
 use rustlearn::prelude::*;
 use rustlearn::trees::decision_tree;
 use rustlearn::ensemble::random_forest::Hyperparameters;
-use rustlearn::metrics::accuracy_score;
-
-use rand::{thread_rng, Rng, StdRng, SeedableRng};
 
+use rand::{StdRng, SeedableRng};
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::process;
+
+const TREES: usize = 10;
+
+struct Args {
+    train: Option<String>,
+    dataset: Option<String>,
+    snapshot: Option<String>,
+    duplicate_threshold: f32,
+    duplicate_report: Option<String>,
+    split: String,
+    folds: usize,
+    report: Option<String>,
+    target_precision: f32,
+    tf_scheme: TfScheme,
+    norm: Norm,
+}
 
-fn get_train_data() -> Vec<RawPostFeatures> {
+fn get_args() -> Result<Args> {
     let matches = App::new("Model Generator")
                       .version("1.0")
                       .about("Generates a random forest based on a training set")
                       .arg(Arg::with_name("train")
                                .help("The CSV to train on")
-                               .required(true)
+                               .required_unless("dataset")
                                .index(1))
+                      .arg(Arg::with_name("dataset")
+                               .long("dataset")
+                               .takes_value(true)
+                               .conflicts_with("train")
+                               .help("Train on the labeled posts of this SQLite dataset instead"))
+                      .arg(Arg::with_name("snapshot")
+                               .long("snapshot")
+                               .takes_value(true)
+                               .requires("dataset")
+                               .help("Only use the posts tagged with this dataset snapshot"))
+                      .arg(Arg::with_name("duplicate-threshold")
+                               .long("duplicate-threshold")
+                               .takes_value(true)
+                               .help("The Jaccard similarity above which posts are considered \
+                                      near-duplicates. Defaults to 0.8"))
+                      .arg(Arg::with_name("duplicate-report")
+                               .long("duplicate-report")
+                               .takes_value(true)
+                               .help("Write the clusters of near-duplicates found to this file"))
+                      .arg(Arg::with_name("split")
+                               .long("split")
+                               .takes_value(true)
+                               .possible_values(&["shuffled",
+                                                  "stratified",
+                                                  "author",
+                                                  "duplicate",
+                                                  "chronological"])
+                               .help("How posts are split into cross validation folds. \
+                                      `author` and `duplicate` keep the posts of an author or \
+                                      of a cluster of near-duplicates in the same fold. \
+                                      Defaults to stratified"))
+                      .arg(Arg::with_name("folds")
+                               .long("folds")
+                               .takes_value(true)
+                               .help("The number of cross validation folds, at least 2. \
+                                      Defaults to 10"))
+                      .arg(Arg::with_name("report")
+                               .long("report")
+                               .takes_value(true)
+                               .help("Append the evaluation report as a JSON line to this file"))
+                      .arg(Arg::with_name("target-precision")
+                               .long("target-precision")
+                               .takes_value(true)
+                               .help("The cross validated precision every label's threshold is \
+                                      tuned for, predictions below the threshold are abstained \
+                                      on. Defaults to 0.95"))
+                      .arg(Arg::with_name("tf-scheme")
+                               .long("tf-scheme")
+                               .takes_value(true)
+                               .possible_values(&["raw", "log", "binary", "augmented"])
+                               .help("How word counts are weighted before their inverse document \
+                                      frequency is applied. Defaults to raw"))
+                      .arg(Arg::with_name("norm")
+                               .long("norm")
+                               .takes_value(true)
+                               .possible_values(&["none", "l1", "l2"])
+                               .help("How the TF-IDF word columns of every post are normalized. \
+                                      Defaults to l2"))
                       .get_matches();
 
-    let train_path = matches.value_of("train").unwrap();
-
-    let mut rdr = csv::Reader::from_file(train_path).unwrap();
-
-    let posts: Vec<RawPostFeatures> = rdr.decode()
-                                         .map(|raw_post| raw_post.unwrap())
-                                         .collect();
+    let duplicate_threshold = match matches.value_of("duplicate-threshold") {
+        Some(t) => try!(parse_fraction("duplicate-threshold", t)),
+        None => DedupConfig::default().threshold,
+    };
 
+    let folds = match matches.value_of("folds") {
+        Some(f) => try!(parse_number("folds", f)),
+        None => 10,
+    };
 
-    let mut posts: Vec<RawPostFeatures> = posts.into_iter()
-                                               .filter(|raw_post| raw_post.selftext.len() > 8)
-                                               .collect();
+    let target_precision = match matches.value_of("target-precision") {
+        Some(p) => try!(parse_fraction("target-precision", p)),
+        None => 0.95,
+    };
 
-    posts.sort_by(|a, b| a.title.cmp(&b.title));
-    dedup_by(&mut posts, |a, b| a.title == b.title);
-    posts
+    Ok(Args {
+        train: matches.value_of("train").map(String::from),
+        dataset: matches.value_of("dataset").map(String::from),
+        snapshot: matches.value_of("snapshot").map(String::from),
+        duplicate_threshold: duplicate_threshold,
+        duplicate_report: matches.value_of("duplicate-report").map(String::from),
+        split: matches.value_of("split").unwrap_or("stratified").to_owned(),
+        folds: folds,
+        report: matches.value_of("report").map(String::from),
+        target_precision: target_precision,
+        tf_scheme: match matches.value_of("tf-scheme") {
+            Some("log") => TfScheme::Log,
+            Some("binary") => TfScheme::Binary,
+            Some("augmented") => TfScheme::Augmented,
+            _ => TfScheme::Raw,
+        },
+        norm: match matches.value_of("norm") {
+            Some("none") => Norm::None,
+            Some("l1") => Norm::L1,
+            _ => Norm::L2,
+        },
+    })
 }
 
+/// Reads the training posts along with the clusters of near-duplicates among them
+fn get_train_data(args: &Args) -> Result<(Vec<RawPostFeatures>, DuplicateClusters)> {
+    let posts: Vec<RawPostFeatures> = match args.dataset {
+        Some(ref path) => {
+            let snapshot = args.snapshot.as_ref().map(|s| s.as_str());
+            try!(try!(Dataset::open(path)).labeled_posts(snapshot))
+        }
+        None => try!(read_posts(args.train.as_ref().unwrap())),
+    };
 
-fn extract_post_features(raw_posts: &[RawPostFeatures]) -> (Vec<ProcessedPostFeatures>, Array) {
-    let selfs: Vec<_> = raw_posts.iter().map(|r| convert_is_self(r.is_self)).collect();
-    let downs: Vec<_> = raw_posts.iter().map(|r| r.downs as f32).collect();
-    let ups: Vec<_> = raw_posts.iter().map(|r| r.ups as f32).collect();
-    let scores: Vec<_> = raw_posts.iter().map(|r| r.score as f32).collect();
-    let mut authors: Vec<&str> = raw_posts.iter().map(|r| r.author.as_ref()).collect();
-
-    write_list(&authors[..], "./data/all_authors");
-    let rust_authors: Vec<&str> = raw_posts.iter()
-                                           .filter_map(|r| if r.subreddit == "rust" {
-                                               Some(r.author.as_ref())
-                                           } else {
-                                               None
-                                           })
+    let posts: Vec<RawPostFeatures> = posts.into_iter()
+                                           .filter(|raw_post| raw_post.selftext.len() > 8)
                                            .collect();
-    let posts: Vec<&str> = raw_posts.iter().map(|r| r.selftext.as_ref()).collect();
-    let post_lens: Vec<f32> = raw_posts.iter().map(|r| r.selftext.len() as f32).collect();
-
-    let titles: Vec<&str> = raw_posts.iter().map(|r| r.title.as_ref()).collect();
-    let subreddits: Vec<_> = raw_posts.iter().map(|r| r.subreddit.as_ref()).collect();
-    let sub_floats = subs_to_float(&subreddits[..]);
-
-    let interesting_words = load_list("./static_data/words_of_interest");
 
-    let mut terms = Vec::new();
-
-    for (post, title) in posts.iter().zip(titles.iter()) {
-        let mut comb = String::new();
-        comb.push_str(post);
-        comb.push_str(" ");
-        comb.push_str(title);
-        terms.push(comb);
+    let config = DedupConfig { threshold: args.duplicate_threshold, ..DedupConfig::default() };
+    let clusters = try!(find_duplicates(&posts, &config));
+    if let Some(ref path) = args.duplicate_report {
+        try!(clusters.write_report(&posts, try!(File::create(path))));
     }
+    Ok((posts, clusters))
+}
 
-    let terms: Vec<&str> = terms.iter().map(|s| s.as_str()).collect();
-
-    let term_frequencies = time!(interesting_word_freq(&terms[..], &interesting_words[..]));
-    let symbol_frequences = time!(symbol_counts(&posts[..]));
-    let rust_regexes = time!(check_for_code(&posts[..]));
+fn train_forest(x: &Array, y: &Array) -> Result<Forest> {
+    let tree_params = decision_tree::Hyperparameters::new(x.cols());
+    let mut model = Hyperparameters::new(tree_params, TREES)
+                        .rng(StdRng::from_seed(&[100]))
+                        .one_vs_rest();
+    try!(model.fit_parallel(x, y, 8).map_err(Error::Learner));
+    Ok(model)
+}
 
-    let author_popularity = time!(convert_author_to_popularity(&authors[..], &rust_authors[..]));
+fn run() -> Result<()> {
+    let args = try!(get_args());
+    let (all_posts, clusters) = try!(get_train_data(&args));
+    let posts = dedup_posts(all_posts.clone(), &clusters);
+
+    let interesting_words = try!(load_list("./static_data/words_of_interest"));
+    let lexicons = try!(load_lexicons("./static_data/lexicons"));
+    let fit_pipeline = |train: &[RawPostFeatures]| {
+        FeaturePipeline::fit(train, interesting_words.clone())
+            .with_lexicons(lexicons.clone())
+            .with_term_weighting(args.tf_scheme, args.norm)
+    };
 
-    authors.sort();
-    write_list(&rust_authors[..], "./data/rust_author_list");
+    // Near-duplicates are only kept when they are grouped into the same fold
+    let (cv_posts, strategy) = match args.split.as_str() {
+        "shuffled" => (&posts, SplitStrategy::Shuffled),
+        "author" => (&posts, SplitStrategy::Grouped(author_groups(&posts))),
+        "duplicate" => (&all_posts, SplitStrategy::Grouped(clusters.assignments().to_vec())),
+        "chronological" => (&posts, SplitStrategy::Chronological),
+        _ => (&posts, SplitStrategy::Stratified),
+    };
 
-    let mut processed = Vec::with_capacity(raw_posts.len());
+    let folds = try!(split(cv_posts, &strategy, args.folds, &mut StdRng::from_seed(&[100])));
+    let outcomes = try!(time!(cross_validate(cv_posts, &folds, &fit_pipeline, train_forest)));
 
-    for index in 0..raw_posts.len() {
-        let p = ProcessedPostFeatures {
-            is_self: selfs[index],
-            author_popularity: author_popularity[index],
-            downs: downs[index],
-            ups: ups[index],
-            score: scores[index],
-            word_freq: term_frequencies[index].clone(),
-            symbol_freq: symbol_frequences[index].clone(),
-            post_len: post_lens[index],
-            regex_matches: rust_regexes[index].clone(),
-        };
-        processed.push(p);
+    let report = EvaluationReport::from_outcomes(&outcomes, 10);
+    println!("{}", report);
+    if let Some(ref path) = args.report {
+        let mut f = try!(OpenOptions::new().create(true).append(true).open(path));
+        try!(writeln!(f, "{}", try!(report.to_json())));
     }
 
-    (processed, Array::from(sub_floats))
-}
+    let pipeline = fit_pipeline(&posts[..]);
+    let (feat_matrix, ground_truth) =
+        time!((pipeline.transform(&posts[..]), pipeline.targets(&posts[..])));
+    let model = try!(train_forest(&feat_matrix, &ground_truth));
 
-fn construct_matrix(post_features: &[ProcessedPostFeatures]) -> Array {
-    let auth_pop: Vec<_> = post_features.iter().map(|p| p.author_popularity).collect();
-    let downs: Vec<_> = post_features.iter().map(|p| p.downs).collect();
-    let ups: Vec<_> = post_features.iter().map(|p| p.ups).collect();
-    let score: Vec<_> = post_features.iter().map(|p| p.score).collect();
-    let post_lens: Vec<_> = post_features.iter().map(|p| p.post_len).collect();
-
-    let feature_count = post_features.iter().last().unwrap().word_freq.iter().count();
-    let feature_count = feature_count +
-                        post_features.iter().last().unwrap().symbol_freq.iter().count();
-    let feature_count = feature_count +
-                        post_features.iter().last().unwrap().regex_matches.iter().count();
-
-    let term_frequencies: Vec<_> = post_features.iter().map(|p| &p.word_freq[..]).collect();
-    let symbol_frequencies: Vec<_> = post_features.iter().map(|p| &p.symbol_freq[..]).collect();
-    let regex_matches: Vec<_> = post_features.iter().map(|p| &p.regex_matches[..]).collect();
-
-    let feature_count = feature_count + 5;
-
-    let mut features = Vec::with_capacity(feature_count * post_features.len());
-
-    for index in 0..post_features.len() {
-        let row = vec![auth_pop[index], downs[index], ups[index], score[index], post_lens[index]];
-        features.extend_from_slice(&row[..]);
-        features.extend_from_slice(term_frequencies[index]);
-        features.extend_from_slice(symbol_frequencies[index]);
-        features.extend_from_slice(regex_matches[index]);
+    let thresholds = tune_thresholds(&outcomes, pipeline.labels(), args.target_precision);
+    for (label, threshold) in pipeline.labels().iter().zip(&thresholds) {
+        println!("threshold {} {}", label, threshold);
     }
 
-    let mut features = Array::from(features);
-    features.reshape(post_features.len(), feature_count);
-    features
+    let metadata = TrainingMetadata::new(posts.len(), TREES, report.accuracy.mean);
+    let bundle = ModelBundle::new(pipeline, model, metadata);
+    let bundle = try!(try!(bundle.with_thresholds(thresholds)).with_feature_stats(&feat_matrix));
+    bundle.save("./models/model_bundle")
 }
 
 fn main() {
-    // Deserialize raw reddit post features from an input file, deduplicate by the title, and
-    // then shuffle them.
-    let mut posts: Vec<_> = {
-        let mut posts = get_train_data();
-        let mut rng = thread_rng();
-        rng.shuffle(&mut posts);
-        // posts.into_iter().take(10).collect()
-        posts
-    };
-
-    let (features, ground_truth) = extract_post_features(&posts[..]);
-    let feat_matrix: Array = construct_matrix(&features[..]);
-
-    let tree_params = decision_tree::Hyperparameters::new(feat_matrix.cols());
-
-    let mut model = Hyperparameters::new(tree_params, 10)
-                        .rng(StdRng::from_seed(&[100]))
-                        .one_vs_rest();
-
-    model.fit_parallel(&feat_matrix, &ground_truth, 8).unwrap();
-    serialize_to_file(&model, "./models/rustlearnrf");
-
-    let no_splits = 10;
-
-    let mut cv = CrossValidation::new(feat_matrix.rows(), no_splits);
-    cv.set_rng(StdRng::from_seed(&[100]));
-
-    let mut test_accuracy = 0.0;
-    for (train_idx, test_idx) in cv {
-
-        let x_train = feat_matrix.get_rows(&train_idx);
-        let x_test = feat_matrix.get_rows(&test_idx);
-
-        let y_train = ground_truth.get_rows(&train_idx);
-        model.fit_parallel(&x_train, &y_train, 8).unwrap();
-        let test_prediction = model.predict(&x_test).unwrap();
-
-        // println!("test_prediction {:#?}", test_prediction);
-        test_accuracy += accuracy_score(&ground_truth.get_rows(&test_idx), &test_prediction);
+    if let Err(e) = run() {
+        let _ = writeln!(io::stderr(), "error: {}", e);
+        process::exit(1);
     }
-    test_accuracy /= no_splits as f32;
-
-    println!("Accuracy {}", test_accuracy);
 }
 
 #[cfg(test)]
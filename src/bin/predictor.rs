@@ -1,161 +1,216 @@
 #![feature(test, custom_derive, plugin)]
+#![plugin(serde_macros)]
 
-#[macro_use(time)]
 extern crate playrust_alert;
 
 extern crate clap;
 extern crate csv;
 extern crate rustc_serialize;
 extern crate rustlearn;
+extern crate serde_json;
 
-use clap::{Arg, App};
-
-use playrust_alert::reddit::{RawPostFeatures, ProcessedPostFeatures, get_posts, RedditClient};
-use playrust_alert::feature_extraction::{convert_author_to_popularity, convert_is_self,
-                                         subs_to_float, symbol_counts, interesting_word_freq,
-                                         check_for_code};
-use playrust_alert::util::{load_list, deserialize_from_file};
-
-use rustlearn::prelude::*;
-use rustlearn::ensemble::random_forest::RandomForest;
-
-fn normalize_post_features(raw_posts: &[RawPostFeatures]) -> (Vec<ProcessedPostFeatures>, Vec<f32>) {
-    let selfs: Vec<_> = raw_posts.iter().map(|r| convert_is_self(r.is_self)).collect();
-    let downs: Vec<_> = raw_posts.iter().map(|r| r.downs as f32).collect();
-    let ups: Vec<_> = raw_posts.iter().map(|r| r.ups as f32).collect();
-    let scores: Vec<_> = raw_posts.iter().map(|r| r.score as f32).collect();
-    let subreddits: Vec<_> = raw_posts.iter().map(|r| r.subreddit.as_ref()).collect();
-    let sub_floats = subs_to_float(&subreddits[..]);
-
-    let authors: Vec<&str> = raw_posts.iter().map(|s| &s.author[..]).collect();
-    let rust_authors = load_list("./data/rust_author_list");
-    let rust_authors: Vec<_> = rust_authors.iter().map(|s| &s[..]).collect();
-    let titles: Vec<&str> = raw_posts.iter().map(|r| r.title.as_ref()).collect();
-    let posts: Vec<&str> = raw_posts.iter().map(|r| r.selftext.as_ref()).collect();
-    let post_lens: Vec<f32> = raw_posts.iter().map(|r| r.selftext.len() as f32).collect();
-
-    let interesting_words = load_list("./static_data/words_of_interest");
-
-    let mut terms = Vec::new();
-
-    for (post, title) in posts.iter().zip(titles.iter()) {
-        let mut comb = String::new();
-        comb.push_str(post);
-        comb.push_str(" ");
-        comb.push_str(title);
-        terms.push(comb);
-    }
+use clap::{Arg, App, ArgMatches, SubCommand};
 
-    let terms: Vec<&str> = terms.iter().map(|s| s.as_str()).collect();
-
-    let term_frequencies = interesting_word_freq(&terms[..], &interesting_words[..]);
-    let symbol_frequencies = symbol_counts(&posts[..]);
-    let rust_regexes = check_for_code(&posts[..]);
-
-    let author_popularity = convert_author_to_popularity(&authors[..], &rust_authors[..]);
-
-    let mut processed = Vec::with_capacity(raw_posts.len());
-
-    for index in 0..raw_posts.len() {
-        let p = ProcessedPostFeatures {
-            is_self: selfs[index],
-            author_popularity: author_popularity[index],
-            downs: downs[index],
-            ups: ups[index],
-            score: scores[index],
-            word_freq: term_frequencies[index].clone(),
-            symbol_freq: symbol_frequencies[index].clone(),
-            post_len: post_lens[index],
-            regex_matches: rust_regexes[index].clone(),
-        };
-        processed.push(p);
-    }
-    (processed, sub_floats)
+use playrust_alert::reddit::{RawPostFeatures, RedditClient, get_posts, parse_post, post_url};
+use playrust_alert::model::{ModelBundle, Outcome};
+use playrust_alert::util::{parse_fraction, read_posts};
+use playrust_alert::{Error, Result};
+
+use serde_json::Value;
+
+use std::io::{self, BufRead, Write};
+use std::process;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Csv,
+    JsonLines,
 }
 
-fn construct_matrix(post_features: &[ProcessedPostFeatures]) -> Array {
-    let auth_pop: Vec<_> = post_features.iter().map(|p| p.author_popularity).collect();
-    let downs: Vec<_> = post_features.iter().map(|p| p.downs).collect();
-    let ups: Vec<_> = post_features.iter().map(|p| p.ups).collect();
-    let score: Vec<_> = post_features.iter().map(|p| p.score).collect();
-    let post_lens: Vec<_> = post_features.iter().map(|p| p.post_len).collect();
-
-    let feature_count = post_features.iter().last().unwrap().word_freq.iter().count();
-    let feature_count = feature_count +
-                        post_features.iter().last().unwrap().symbol_freq.iter().count();
-    let feature_count = feature_count +
-                        post_features.iter().last().unwrap().regex_matches.iter().count();
-
-    let term_frequencies: Vec<_> = post_features.iter().map(|p| &p.word_freq[..]).collect();
-    let symbol_frequencies: Vec<_> = post_features.iter().map(|p| &p.symbol_freq[..]).collect();
-    let regex_matches: Vec<_> = post_features.iter().map(|p| &p.regex_matches[..]).collect();
-
-    let feature_count = feature_count + 5;
-
-    let mut features = Vec::with_capacity(feature_count * post_features.len());
-
-    for index in 0..post_features.len() {
-        let row = vec![auth_pop[index], downs[index], ups[index], score[index], post_lens[index]];
-        features.extend_from_slice(&row[..]);
-        features.extend_from_slice(term_frequencies[index]);
-        features.extend_from_slice(symbol_frequencies[index]);
-        features.extend_from_slice(regex_matches[index]);
-    }
+enum Input {
+    /// A CSV of `RawPostFeatures`
+    Csv(String),
+    /// Post urls or ids
+    Posts(Vec<String>),
+    /// Newline delimited reddit post JSON
+    Stdin,
+}
+
+struct Args {
+    model: String,
+    format: Format,
+    thresholds: Vec<(String, f32)>,
+    input: Input,
+}
+
+/// The result for one post, as written to the output
+#[derive(Serialize, RustcEncodable, Debug)]
+struct Row {
+    id: String,
+    /// The predicted subreddit, or `abstain`
+    outcome: String,
+    /// The most probable subreddit, even when abstaining
+    label: String,
+    probability: f32,
+}
 
-    let mut features = Array::from(features);
-    features.reshape(post_features.len(), feature_count);
-    features
+/// Parses `LABEL=PROBABILITY` overrides of the bundled thresholds
+fn parse_thresholds(matches: &ArgMatches) -> Result<Vec<(String, f32)>> {
+    let mut thresholds = Vec::new();
+    for value in matches.values_of("threshold").into_iter().flat_map(|values| values) {
+        let mut parts = value.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(label), Some(threshold)) if !label.is_empty() => {
+                thresholds.push((label.to_owned(), try!(parse_fraction("threshold", threshold))));
+            }
+            _ => {
+                return Err(Error::Config("threshold must be LABEL=PROBABILITY".to_owned()));
+            }
+        }
+    }
+    Ok(thresholds)
 }
 
-fn get_pred_data() -> Vec<RawPostFeatures> {
+fn get_args() -> Result<Args> {
     let matches = App::new("PlayRust Predictor")
                       .version("1.0")
                       .about("Given a series of reddit posts, predicts which sub they came from")
-                      .arg(Arg::with_name("pred")
-                               .help("The CSV to predict against")
-                               .required(true)
-                               .index(1))
+                      .arg(Arg::with_name("model")
+                               .long("model")
+                               .takes_value(true)
+                               .help("The model bundle to load. Defaults to \
+                                      ./models/model_bundle"))
+                      .arg(Arg::with_name("format")
+                               .long("format")
+                               .takes_value(true)
+                               .possible_values(&["csv", "jsonl"])
+                               .help("The output format. Defaults to csv"))
+                      .arg(Arg::with_name("threshold")
+                               .long("threshold")
+                               .takes_value(true)
+                               .multiple(true)
+                               .number_of_values(1)
+                               .help("LABEL=PROBABILITY, overrides the probability a label \
+                                      must reach to be predicted instead of abstaining"))
+                      .subcommand(SubCommand::with_name("csv")
+                                      .about("Classifies the posts of a CSV")
+                                      .arg(Arg::with_name("csv").required(true).index(1)))
+                      .subcommand(SubCommand::with_name("url")
+                                      .about("Fetches and classifies posts by url or id")
+                                      .arg(Arg::with_name("posts")
+                                               .required(true)
+                                               .multiple(true)
+                                               .index(1)))
+                      .subcommand(SubCommand::with_name("stdin")
+                                      .about("Classifies newline delimited reddit post JSON \
+                                              read from stdin"))
                       .get_matches();
 
-    let pred_path = matches.value_of("pred").unwrap();
-
-    let mut rdr = csv::Reader::from_file(pred_path).unwrap();
-
-    rdr.decode()
-       .map(|raw_post| raw_post.unwrap())
-       .collect()
+    let input = match matches.subcommand() {
+        ("csv", Some(m)) => Input::Csv(m.value_of("csv").unwrap().to_owned()),
+        ("url", Some(m)) => {
+            Input::Posts(m.values_of("posts").unwrap().map(String::from).collect())
+        }
+        ("stdin", _) => Input::Stdin,
+        _ => {
+            let _ = writeln!(io::stderr(), "{}", matches.usage());
+            process::exit(1);
+        }
+    };
+
+    Ok(Args {
+        model: matches.value_of("model").unwrap_or("./models/model_bundle").to_owned(),
+        format: match matches.value_of("format") {
+            Some("jsonl") => Format::JsonLines,
+            _ => Format::Csv,
+        },
+        thresholds: try!(parse_thresholds(&matches)),
+        input: input,
+    })
 }
 
-// fn predict(r: &mut Request) -> PencilResult {
-//     let url = r.get_json().unwrap().as_string();
-//     let reddit_client = reddit::RedditClient::new();
-//     let raw_features = vec![reddit_client.get_raw_features_from_url(&url)];
-//
-//     let raw_posts: Vec<_> = raw_posts.into_iter().filter(|p| p.subreddit == "playrust").collect();
-//     let (features, _) = normalize_post_features(&raw_posts[..2]);
-//     let feat_matrix = construct_matrix(&features[..]);
-//
-//     let rf = load_model();
-//     let pred = rf.predict(&feat_matrix).unwrap();
-//     let sub = if pred.round() == 0 {
-//         "playrust"
-//     } else {
-//         "rust"
-//     };
-//
-//     Ok(Response::from(&sub))
-// }
+fn read_input(input: &Input) -> Result<Vec<RawPostFeatures>> {
+    match *input {
+        Input::Csv(ref path) => read_posts(path),
+        Input::Posts(ref posts) => {
+            let mut client = RedditClient::new();
+            let mut raw_posts = Vec::with_capacity(posts.len());
+            for post in posts {
+                let raw = try!(client.get_raw_features_from_url(&post_url(post)));
+                raw_posts.extend(try!(get_posts(raw)));
+            }
+            Ok(raw_posts)
+        }
+        Input::Stdin => {
+            let stdin = io::stdin();
+            let mut raw_posts = Vec::new();
+            for (n, line) in stdin.lock().lines().enumerate() {
+                let line = try!(line);
+                if line.trim().is_empty() {
+                    continue;
+                }
+                // A single malformed line should not abort the whole batch
+                let post = serde_json::from_str::<Value>(&line)
+                               .map_err(Error::from)
+                               .and_then(|value| parse_post(&value));
+                match post {
+                    Ok(post) => raw_posts.push(post),
+                    Err(e) => {
+                        let _ = writeln!(io::stderr(), "skipping line {}: {}", n + 1, e);
+                    }
+                }
+            }
+            Ok(raw_posts)
+        }
+    }
+}
 
-fn main() {
+fn run() -> Result<()> {
+    let args = try!(get_args());
 
-    let rf: RandomForest = deserialize_from_file("./models/rustlearnrf");
+    let mut bundle = try!(ModelBundle::load(&args.model));
+    for &(ref label, threshold) in &args.thresholds {
+        if !bundle.set_threshold(label, threshold) {
+            let _ = writeln!(io::stderr(), "warning: the model has no label {}", label);
+        }
+    }
 
-    let mut reddit_client = RedditClient::new();
-    let raw = reddit_client.get_raw_features_from_url("https://www.reddit.com/r/rust/comments/4tz6e5/are_aliased_mutable_raw_pointers_ub");
-    let raw_posts = get_posts(raw);
-    //
-    let (features, _) = time!(normalize_post_features(&raw_posts[..]));
-    let feat_matrix = time!(construct_matrix(&features[..]));
-    println!("{:?}", time!(rf.predict(&feat_matrix).unwrap()));
+    let raw_posts = try!(read_input(&args.input));
+    let predictions = try!(bundle.classify(&raw_posts[..]));
+
+    let rows = raw_posts.iter().zip(predictions).map(|(post, prediction)| {
+        Row {
+            id: post.dedup_key().to_owned(),
+            outcome: match prediction.outcome {
+                Outcome::Label(ref label) => label.clone(),
+                Outcome::Abstain => "abstain".to_owned(),
+            },
+            label: prediction.label,
+            probability: prediction.probability,
+        }
+    });
+
+    let stdout = io::stdout();
+    match args.format {
+        Format::Csv => {
+            let mut wtr = csv::Writer::from_writer(stdout.lock());
+            for row in rows {
+                try!(wtr.encode(row));
+            }
+        }
+        Format::JsonLines => {
+            let mut out = stdout.lock();
+            for row in rows {
+                try!(writeln!(out, "{}", try!(serde_json::to_string(&row))));
+            }
+        }
+    }
+    Ok(())
+}
 
+fn main() {
+    if let Err(e) = run() {
+        let _ = writeln!(io::stderr(), "error: {}", e);
+        process::exit(1);
+    }
 }
@@ -0,0 +1,92 @@
+extern crate clap;
+extern crate hyper;
+extern crate playrust_alert;
+
+use clap::{Arg, App};
+use hyper::header::ContentType;
+use hyper::server::{Handler, Request, Response, Server};
+use hyper::status::StatusCode;
+use hyper::uri::RequestUri;
+
+use playrust_alert::model::ModelBundle;
+use playrust_alert::reddit::RedditClient;
+use playrust_alert::service::Service;
+use playrust_alert::transport::HyperTransport;
+use playrust_alert::util::parse_positive;
+use playrust_alert::{Error, Result};
+
+use std::io::{self, Read, Write};
+use std::process;
+
+struct HttpHandler {
+    service: Service<HyperTransport>,
+}
+
+impl Handler for HttpHandler {
+    fn handle<'a, 'k>(&'a self, mut req: Request<'a, 'k>, mut res: Response<'a>) {
+        let mut body = String::new();
+        let response = match req.read_to_string(&mut body) {
+            Ok(_) => {
+                let path = match req.uri {
+                    RequestUri::AbsolutePath(ref path) => path.clone(),
+                    _ => String::new(),
+                };
+                self.service.handle(req.method.as_ref(), &path, &body)
+            }
+            Err(_) => {
+                *res.status_mut() = StatusCode::BadRequest;
+                let _ = res.send(b"{\"error\": \"unreadable request body\"}");
+                return;
+            }
+        };
+
+        *res.status_mut() = StatusCode::from_u16(response.status);
+        res.headers_mut().set(ContentType::json());
+        if let Err(e) = res.send(response.body.as_bytes()) {
+            let _ = writeln!(io::stderr(), "failed to send response: {}", e);
+        }
+    }
+}
+
+fn run() -> Result<()> {
+    let matches = App::new("PlayRust Classification Service")
+                      .version("1.0")
+                      .about("Serves the model over HTTP")
+                      .arg(Arg::with_name("model")
+                               .long("model")
+                               .takes_value(true)
+                               .help("The model bundle to load. Defaults to \
+                                      ./models/model_bundle"))
+                      .arg(Arg::with_name("addr")
+                               .long("addr")
+                               .takes_value(true)
+                               .help("The address to listen on. Defaults to 127.0.0.1:3000"))
+                      .arg(Arg::with_name("threads")
+                               .long("threads")
+                               .takes_value(true)
+                               .help("The number of requests handled concurrently. Defaults \
+                                      to 8"))
+                      .get_matches();
+
+    let threads = match matches.value_of("threads") {
+        Some(t) => try!(parse_positive("threads", t)),
+        None => 8,
+    };
+    let bundle = try!(ModelBundle::load(matches.value_of("model")
+                                               .unwrap_or("./models/model_bundle")));
+    let addr = matches.value_of("addr").unwrap_or("127.0.0.1:3000");
+
+    let handler = HttpHandler { service: Service::new(bundle, RedditClient::new()) };
+    let server = try!(Server::http(addr).map_err(Error::Http));
+
+    println!("listening on {}", addr);
+    try!(server.handle_threads(handler, threads).map_err(Error::Http));
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        let _ = writeln!(io::stderr(), "error: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,240 @@
+extern crate clap;
+extern crate playrust_alert;
+
+use clap::{Arg, App};
+
+use playrust_alert::moderation::{ModerationPolicy, Moderator};
+use playrust_alert::model::{ModelBundle, Outcome};
+use playrust_alert::oauth::{Credentials, OAuth};
+use playrust_alert::reddit::RedditClient;
+use playrust_alert::sink::{AlertSink, JsonLinesSink, load_sinks};
+use playrust_alert::watcher::Watcher;
+use playrust_alert::util::{parse_number, parse_positive};
+use playrust_alert::{Error, Result};
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+struct Args {
+    model: String,
+    subreddit: String,
+    label: String,
+    interval: u64,
+    page_size: u32,
+    base_url: Option<String>,
+    user_agent: Option<String>,
+    credentials: Option<String>,
+    sinks: Option<String>,
+    moderation: Option<String>,
+    audit_log: String,
+    dry_run: bool,
+    once: bool,
+    skip_backlog: bool,
+    seen_capacity: usize,
+}
+
+fn get_args() -> Result<Args> {
+    let matches = App::new("PlayRust Watcher")
+                      .version("1.0")
+                      .about("Polls a subreddit for new posts and alerts on those predicted to \
+                              belong to another subreddit")
+                      .arg(Arg::with_name("model")
+                               .long("model")
+                               .takes_value(true)
+                               .help("The model bundle to load. Defaults to \
+                                      ./models/model_bundle"))
+                      .arg(Arg::with_name("subreddit")
+                               .long("subreddit")
+                               .takes_value(true)
+                               .help("The subreddit to watch. Defaults to rust"))
+                      .arg(Arg::with_name("label")
+                               .long("label")
+                               .takes_value(true)
+                               .help("The predicted subreddit to alert on. Defaults to playrust"))
+                      .arg(Arg::with_name("interval")
+                               .long("interval")
+                               .takes_value(true)
+                               .help("Seconds between polls. Defaults to 60"))
+                      .arg(Arg::with_name("page-size")
+                               .long("page-size")
+                               .takes_value(true)
+                               .help("The number of newest posts fetched per poll. Defaults to \
+                                      25"))
+                      .arg(Arg::with_name("base-url")
+                               .long("base-url")
+                               .takes_value(true)
+                               .help("Where to reach reddit, e.g. a local mock_reddit server"))
+                      .arg(Arg::with_name("user-agent")
+                               .long("user-agent")
+                               .takes_value(true)
+                               .help("The User-Agent to identify as to reddit"))
+                      .arg(Arg::with_name("credentials")
+                               .long("credentials")
+                               .takes_value(true)
+                               .help("A JSON file with OAuth credentials. Defaults to the \
+                                      REDDIT_CLIENT_ID, REDDIT_CLIENT_SECRET, REDDIT_USERNAME \
+                                      and REDDIT_PASSWORD environment variables"))
+                      .arg(Arg::with_name("sinks")
+                               .long("sinks")
+                               .takes_value(true)
+                               .help("A JSON file listing where to send alerts. Defaults to \
+                                      JSON lines on stdout"))
+                      .arg(Arg::with_name("moderation")
+                               .long("moderation")
+                               .takes_value(true)
+                               .help("A JSON moderation policy. Alerted posts are reported, \
+                                      replied to or removed depending on their probability"))
+                      .arg(Arg::with_name("audit-log")
+                               .long("audit-log")
+                               .takes_value(true)
+                               .help("Where every moderator action is logged. Defaults to \
+                                      ./moderation_audit.jsonl"))
+                      .arg(Arg::with_name("dry-run")
+                               .long("dry-run")
+                               .help("Log every classification to stderr instead of emitting \
+                                      alerts, and only audit moderator actions"))
+                      .arg(Arg::with_name("once")
+                               .long("once")
+                               .help("Poll a single time and exit"))
+                      .arg(Arg::with_name("skip-backlog")
+                               .long("skip-backlog")
+                               .help("Do not alert on the posts already there at startup"))
+                      .arg(Arg::with_name("seen-capacity")
+                               .long("seen-capacity")
+                               .takes_value(true)
+                               .help("The number of post ids remembered. Defaults to 10000"))
+                      .get_matches();
+
+    let interval = match matches.value_of("interval") {
+        Some(i) => try!(parse_positive("interval", i)) as u64,
+        None => 60,
+    };
+    let page_size = match matches.value_of("page-size") {
+        Some(p) => try!(parse_number("page-size", p)),
+        None => 25,
+    };
+    if page_size == 0 {
+        return Err(Error::Config("--page-size must be at least 1".to_owned()));
+    }
+    // Remembering no ids would alert on every post again on every poll
+    let seen_capacity = match matches.value_of("seen-capacity") {
+        Some(c) => try!(parse_positive("seen-capacity", c)),
+        None => 10000,
+    };
+
+    Ok(Args {
+        model: matches.value_of("model").unwrap_or("./models/model_bundle").to_owned(),
+        subreddit: matches.value_of("subreddit").unwrap_or("rust").to_owned(),
+        label: matches.value_of("label").unwrap_or("playrust").to_owned(),
+        interval: interval,
+        page_size: page_size,
+        base_url: matches.value_of("base-url").map(String::from),
+        user_agent: matches.value_of("user-agent").map(String::from),
+        credentials: matches.value_of("credentials").map(String::from),
+        sinks: matches.value_of("sinks").map(String::from),
+        moderation: matches.value_of("moderation").map(String::from),
+        audit_log: matches.value_of("audit-log").unwrap_or("./moderation_audit.jsonl").to_owned(),
+        dry_run: matches.is_present("dry-run"),
+        once: matches.is_present("once"),
+        skip_backlog: matches.is_present("skip-backlog"),
+        seen_capacity: seen_capacity,
+    })
+}
+
+fn run() -> Result<()> {
+    let args = try!(get_args());
+    let bundle = try!(ModelBundle::load(&args.model));
+
+    let mut client = RedditClient::new();
+    if let Some(ref base_url) = args.base_url {
+        client = client.base_url(base_url);
+    }
+    if let Some(ref user_agent) = args.user_agent {
+        client = client.user_agent(user_agent);
+    }
+    let credentials = args.credentials.as_ref().map(|c| c.as_str());
+    if let Some(credentials) = try!(Credentials::load(credentials)) {
+        client = client.oauth(OAuth::new(credentials));
+    }
+
+    let mut sinks: Vec<Box<AlertSink>> = match args.sinks {
+        Some(ref path) => try!(load_sinks(path)),
+        None => vec![Box::new(JsonLinesSink::stdout()) as Box<AlertSink>],
+    };
+
+    let mut moderator = match args.moderation {
+        Some(ref path) => {
+            let policy = try!(ModerationPolicy::load(path));
+            let dry_run = policy.dry_run || args.dry_run;
+            let audit = try!(OpenOptions::new().create(true).append(true).open(&args.audit_log));
+            Some(Moderator::new(policy, &bundle, audit).dry_run(dry_run))
+        }
+        None => None,
+    };
+
+    let mut watcher = Watcher::new(&args.subreddit, &args.label)
+                          .page_size(args.page_size)
+                          .skip_backlog(args.skip_backlog)
+                          .seen_capacity(args.seen_capacity);
+
+    loop {
+        // A failed poll, e.g. reddit being down, is retried on the next interval
+        match watcher.poll_once(&mut client, &bundle) {
+            Ok(poll) => {
+                if args.dry_run {
+                    for &(ref post, ref prediction) in &poll.classified {
+                        let outcome = match prediction.outcome {
+                            Outcome::Label(ref label) => label.as_str(),
+                            Outcome::Abstain => "abstain",
+                        };
+                        let _ = writeln!(io::stderr(),
+                                         "{} {} ({} {:.3}) {}",
+                                         post.dedup_key(),
+                                         outcome,
+                                         prediction.label,
+                                         prediction.probability,
+                                         post.title);
+                    }
+                } else {
+                    // One unreachable sink should not keep the alert from the others
+                    for alert in &poll.alerts {
+                        for sink in &mut sinks {
+                            if let Err(e) = sink.send(alert) {
+                                let _ = writeln!(io::stderr(), "failed to send alert: {}", e);
+                            }
+                        }
+                    }
+                }
+
+                if let Some(ref mut moderator) = moderator {
+                    for alert in &poll.alerts {
+                        if let Err(e) = moderator.act(&mut client, alert) {
+                            let _ = writeln!(io::stderr(),
+                                             "moderation of {} failed: {}",
+                                             alert.id,
+                                             e);
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                let _ = writeln!(io::stderr(), "poll failed: {}", e);
+            }
+        }
+
+        if args.once {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_secs(args.interval));
+    }
+}
+
+fn main() {
+    if let Err(e) = run() {
+        let _ = writeln!(io::stderr(), "error: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,363 @@
+use rand::Rng;
+use rustlearn::prelude::*;
+
+use error::{Error, Result};
+use model::Forest;
+use pipeline::FeaturePipeline;
+use reddit::RawPostFeatures;
+
+use std::cmp;
+use std::collections::BTreeMap;
+
+/// How posts are assigned to cross validation folds
+#[derive(Debug, Clone)]
+pub enum SplitStrategy {
+    /// Shuffled posts are dealt to the folds in turn
+    Shuffled,
+    /// Every fold gets roughly the label distribution of the whole set
+    Stratified,
+    /// `groups[i]` is the group of post `i`. The posts of a group always end up in the same
+    /// fold, so e.g. an author or a cluster of duplicates never appears on both sides of a split.
+    Grouped(Vec<usize>),
+    /// Posts are ordered by `created_utc` and cut into `folds + 1` consecutive chunks. Fold `k`
+    /// trains on the first `k + 1` chunks and tests on the one after them, so the model never
+    /// sees posts from the future.
+    Chronological,
+}
+
+/// The indices of the posts to train and test one fold on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fold {
+    pub train: Vec<usize>,
+    pub test: Vec<usize>,
+}
+
+/// Groups posts by their author
+pub fn author_groups(posts: &[RawPostFeatures]) -> Vec<usize> {
+    let mut ids = BTreeMap::new();
+    posts.iter()
+         .map(|post| {
+             let next = ids.len();
+             *ids.entry(post.author.as_str()).or_insert(next)
+         })
+         .collect()
+}
+
+/// Builds the train/test split of every fold from the fold each post was assigned to
+fn folds_from_assignment(assignment: &[usize], folds: usize) -> Vec<Fold> {
+    (0..folds)
+        .map(|fold| {
+            let (test, train): (Vec<usize>, Vec<usize>) = (0..assignment.len())
+                                                              .partition(|&i| {
+                                                                  assignment[i] == fold
+                                                              });
+            Fold {
+                train: train,
+                test: test,
+            }
+        })
+        .collect()
+}
+
+/// Splits `posts` into `folds` folds with `strategy`. Fewer than two folds would leave nothing
+/// to train on, so they are rejected.
+pub fn split<R: Rng>(posts: &[RawPostFeatures],
+                     strategy: &SplitStrategy,
+                     folds: usize,
+                     rng: &mut R)
+                     -> Result<Vec<Fold>> {
+    if folds < 2 {
+        return Err(Error::Config(format!("at least 2 folds are required, got {}", folds)));
+    }
+    let mut assignment = vec![0; posts.len()];
+
+    match *strategy {
+        SplitStrategy::Shuffled => {
+            let mut order: Vec<usize> = (0..posts.len()).collect();
+            rng.shuffle(&mut order);
+            for (n, &index) in order.iter().enumerate() {
+                assignment[index] = n % folds;
+            }
+        }
+        SplitStrategy::Stratified => {
+            let mut by_label: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+            for (index, post) in posts.iter().enumerate() {
+                by_label.entry(post.subreddit.as_str()).or_insert_with(Vec::new).push(index);
+            }
+
+            // Continue dealing where the previous label stopped, so the smaller folds catch up
+            let mut n = 0;
+            for indices in by_label.values_mut() {
+                rng.shuffle(indices);
+                for &index in indices.iter() {
+                    assignment[index] = n % folds;
+                    n += 1;
+                }
+            }
+        }
+        SplitStrategy::Grouped(ref groups) => {
+            if groups.len() != posts.len() {
+                return Err(Error::Config(format!("{} groups given for {} posts",
+                                                 groups.len(),
+                                                 posts.len())));
+            }
+            let mut members: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+            for (index, &group) in groups.iter().enumerate() {
+                members.entry(group).or_insert_with(Vec::new).push(index);
+            }
+
+            // Largest groups first, each into the currently smallest fold
+            let mut members: Vec<Vec<usize>> = members.into_iter().map(|(_, m)| m).collect();
+            rng.shuffle(&mut members);
+            members.sort_by(|a, b| b.len().cmp(&a.len()));
+
+            let mut sizes = vec![0; folds];
+            for group in members {
+                let fold = (0..folds).min_by_key(|&f| sizes[f]).unwrap();
+                sizes[fold] += group.len();
+                for index in group {
+                    assignment[index] = fold;
+                }
+            }
+        }
+        SplitStrategy::Chronological => {
+            let mut order: Vec<usize> = (0..posts.len()).collect();
+            order.sort_by(|&a, &b| {
+                posts[a].created_utc
+                        .partial_cmp(&posts[b].created_utc)
+                        .unwrap_or(cmp::Ordering::Equal)
+            });
+
+            let chunks = folds + 1;
+            let chunk_of = |n: usize| n * chunks / cmp::max(posts.len(), 1);
+            return Ok((0..folds)
+                          .map(|fold| {
+                              Fold {
+                                  train: order.iter()
+                                              .enumerate()
+                                              .filter(|&(n, _)| chunk_of(n) <= fold)
+                                              .map(|(_, &i)| i)
+                                              .collect(),
+                                  test: order.iter()
+                                             .enumerate()
+                                             .filter(|&(n, _)| chunk_of(n) == fold + 1)
+                                             .map(|(_, &i)| i)
+                                             .collect(),
+                              }
+                          })
+                          .collect());
+        }
+    }
+
+    Ok(folds_from_assignment(&assignment, folds))
+}
+
+/// The predictions of the model trained on one fold for its test posts
+#[derive(Debug, Clone)]
+pub struct FoldOutcome {
+    /// Indices of the test posts that were scored. Posts with a label absent from the training
+    /// fold can not be scored and are left out.
+    pub test: Vec<usize>,
+    /// Ground truth label of every scored post
+    pub truth: Vec<f32>,
+    /// One row per scored post with the score of every label, in label order
+    pub scores: Array,
+    /// Label names, indexed by label
+    pub labels: Vec<String>,
+}
+
+impl FoldOutcome {
+    /// The highest scoring label of every scored post
+    pub fn predicted(&self) -> Vec<f32> {
+        (0..self.scores.rows())
+            .map(|row| {
+                (0..self.scores.cols()).fold(0, |best, col| {
+                    if self.scores.get(row, col) > self.scores.get(row, best) {
+                        col
+                    } else {
+                        best
+                    }
+                }) as f32
+            })
+            .collect()
+    }
+
+    /// Fraction of scored posts whose highest scoring label is the true one
+    pub fn accuracy(&self) -> f32 {
+        if self.truth.is_empty() {
+            return 0.0;
+        }
+        let correct = self.predicted()
+                          .iter()
+                          .zip(&self.truth)
+                          .filter(|&(p, t)| p == t)
+                          .count();
+        correct as f32 / self.truth.len() as f32
+    }
+}
+
+fn select(posts: &[RawPostFeatures], indices: &[usize]) -> Vec<RawPostFeatures> {
+    indices.iter().map(|&i| posts[i].clone()).collect()
+}
+
+/// Runs every fold: `fit_pipeline` is fit on the training posts only, so nothing learned from
+/// the test posts (like author popularity) leaks into the features, then `train` fits a forest
+/// on the transformed training posts, which scores the test posts.
+pub fn cross_validate<P, T>(posts: &[RawPostFeatures],
+                            folds: &[Fold],
+                            mut fit_pipeline: P,
+                            mut train: T)
+                            -> Result<Vec<FoldOutcome>>
+    where P: FnMut(&[RawPostFeatures]) -> FeaturePipeline,
+          T: FnMut(&Array, &Array) -> Result<Forest>
+{
+    let mut outcomes = Vec::with_capacity(folds.len());
+
+    for fold in folds {
+        let train_posts = select(posts, &fold.train);
+        let pipeline = fit_pipeline(&train_posts);
+        let forest = try!(train(&pipeline.transform(&train_posts),
+                                &pipeline.targets(&train_posts)));
+
+        let (test, truth): (Vec<usize>, Vec<f32>) =
+            fold.test
+                .iter()
+                .filter_map(|&i| pipeline.label_of(&posts[i].subreddit).map(|label| (i, label)))
+                .unzip();
+
+        let scores = if test.is_empty() {
+            Array::zeros(0, pipeline.labels().len())
+        } else {
+            let x_test = pipeline.transform(&select(posts, &test));
+            try!(forest.decision_function(&x_test).map_err(Error::Learner))
+        };
+
+        outcomes.push(FoldOutcome {
+            test: test,
+            truth: truth,
+            scores: scores,
+            labels: pipeline.labels().to_vec(),
+        });
+    }
+
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::Error;
+    use pipeline::FeaturePipeline;
+    use reddit::RawPostFeatures;
+
+    use rand::{StdRng, SeedableRng};
+    use rustlearn::prelude::*;
+    use rustlearn::ensemble::random_forest::Hyperparameters;
+    use rustlearn::trees::decision_tree;
+
+    use std::collections::BTreeSet;
+
+    fn posts() -> Vec<RawPostFeatures> {
+        (0..12)
+            .map(|i| {
+                RawPostFeatures {
+                    author: format!("author{}", i % 4),
+                    subreddit: if i % 3 == 0 { "playrust" } else { "rust" }.to_owned(),
+                    selftext: if i % 3 == 0 { "wipe day" } else { "fn main() {}" }.to_owned(),
+                    created_utc: (12 - i) as f64,
+                    ..RawPostFeatures::default()
+                }
+            })
+            .collect()
+    }
+
+    fn rng() -> StdRng {
+        StdRng::from_seed(&[100])
+    }
+
+    fn check_partition(folds: &[Fold], len: usize) {
+        let tested: BTreeSet<usize> = folds.iter().flat_map(|f| f.test.iter().cloned()).collect();
+        assert_eq!(tested.len(), len);
+        for fold in folds {
+            assert_eq!(fold.train.len() + fold.test.len(), len);
+        }
+    }
+
+    #[test]
+    fn test_stratified() {
+        let posts = posts();
+        let folds = split(&posts, &SplitStrategy::Stratified, 4, &mut rng()).unwrap();
+        check_partition(&folds, posts.len());
+
+        for fold in &folds {
+            let playrust = fold.test.iter().filter(|&&i| posts[i].subreddit == "playrust");
+            assert_eq!(playrust.count(), 1);
+        }
+    }
+
+    #[test]
+    fn test_too_few_folds() {
+        let posts = posts();
+        assert!(split(&posts, &SplitStrategy::Shuffled, 0, &mut rng()).is_err());
+        assert!(split(&posts, &SplitStrategy::Stratified, 1, &mut rng()).is_err());
+        assert!(split(&posts, &SplitStrategy::Grouped(vec![0]), 2, &mut rng()).is_err());
+    }
+
+    #[test]
+    fn test_grouped_by_author() {
+        let posts = posts();
+        let groups = author_groups(&posts);
+        let folds = split(&posts, &SplitStrategy::Grouped(groups), 2, &mut rng()).unwrap();
+        check_partition(&folds, posts.len());
+
+        for fold in &folds {
+            let train: BTreeSet<&str> = fold.train
+                                            .iter()
+                                            .map(|&i| posts[i].author.as_str())
+                                            .collect();
+            assert!(fold.test.iter().all(|&i| !train.contains(&posts[i].author[..])));
+        }
+    }
+
+    #[test]
+    fn test_chronological() {
+        let posts = posts();
+        let folds = split(&posts, &SplitStrategy::Chronological, 3, &mut rng()).unwrap();
+
+        assert_eq!(folds.len(), 3);
+        for fold in &folds {
+            let newest_train = fold.train
+                                   .iter()
+                                   .map(|&i| posts[i].created_utc)
+                                   .fold(0.0, f64::max);
+            assert!(fold.test.iter().all(|&i| posts[i].created_utc > newest_train));
+        }
+        assert_eq!(folds[2].train.len(), 9);
+    }
+
+    #[test]
+    fn test_cross_validate() {
+        let posts = posts();
+        let folds = split(&posts, &SplitStrategy::Stratified, 3, &mut rng()).unwrap();
+
+        let train = |x: &Array, y: &Array| {
+            let params = decision_tree::Hyperparameters::new(x.cols());
+            let mut forest = Hyperparameters::new(params, 2).one_vs_rest();
+            try!(forest.fit(x, y).map_err(Error::Learner));
+            Ok(forest)
+        };
+        let outcomes = cross_validate(&posts,
+                                      &folds,
+                                      |train| FeaturePipeline::fit(train, vec![]),
+                                      train)
+                           .unwrap();
+
+        assert_eq!(outcomes.len(), 3);
+        for outcome in &outcomes {
+            assert_eq!(outcome.truth.len(), 4);
+            assert_eq!(outcome.scores.rows(), 4);
+            assert_eq!(outcome.scores.cols(), outcome.labels.len());
+            assert_eq!(outcome.predicted().len(), 4);
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use csv::Writer;
+use fnv::FnvHasher;
+use rusqlite::{self, Connection, Row};
+
+use error::Result;
+use reddit::RawPostFeatures;
+use store::PostStore;
+use util::read_posts;
+
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The label source recorded for labels taken from the subreddit a post was scraped from.
+/// Labels from any other source take precedence over it.
+pub const SUBREDDIT_SOURCE: &'static str = "subreddit";
+
+const SCHEMA: &'static str = "
+    CREATE TABLE IF NOT EXISTS posts (
+        id              TEXT PRIMARY KEY,
+        name            TEXT NOT NULL,
+        is_self         INTEGER NOT NULL,
+        author          TEXT NOT NULL,
+        url             TEXT NOT NULL,
+        downs           INTEGER NOT NULL,
+        ups             INTEGER NOT NULL,
+        score           INTEGER NOT NULL,
+        selftext        TEXT NOT NULL,
+        subreddit       TEXT NOT NULL,
+        title           TEXT NOT NULL,
+        permalink       TEXT NOT NULL,
+        created_utc     REAL NOT NULL,
+        num_comments    INTEGER NOT NULL,
+        link_flair_text TEXT,
+        domain          TEXT NOT NULL,
+        over_18         INTEGER NOT NULL,
+        stickied        INTEGER NOT NULL,
+        upvote_ratio    REAL NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS labels (
+        post_id    TEXT NOT NULL REFERENCES posts(id),
+        label      TEXT NOT NULL,
+        source     TEXT NOT NULL,
+        labeled_at INTEGER NOT NULL,
+        PRIMARY KEY (post_id, source)
+    );
+
+    CREATE TABLE IF NOT EXISTS snapshots (
+        tag     TEXT NOT NULL,
+        post_id TEXT NOT NULL REFERENCES posts(id),
+        PRIMARY KEY (tag, post_id)
+    );
+";
+
+const POST_COLUMNS: &'static str = "p.id, p.name, p.is_self, p.author, p.url, p.downs, p.ups, \
+                                    p.score, p.selftext, p.subreddit, p.title, p.permalink, \
+                                    p.created_utc, p.num_comments, p.link_flair_text, \
+                                    p.domain, p.over_18, p.stickied, p.upvote_ratio";
+
+/// `POST_COLUMNS` with the subreddit replaced by the label of the post: the newest label from a
+/// source other than `SUBREDDIT_SOURCE`, falling back to the subreddit label and then to the
+/// subreddit itself
+fn labeled_post_columns() -> String {
+    format!("p.id, p.name, p.is_self, p.author, p.url, p.downs, p.ups, p.score, p.selftext, \
+             COALESCE((SELECT label FROM labels \
+                       WHERE post_id = p.id AND source != '{source}' \
+                       ORDER BY labeled_at DESC LIMIT 1), \
+                      (SELECT label FROM labels WHERE post_id = p.id AND source = '{source}'), \
+                      p.subreddit), \
+             p.title, p.permalink, p.created_utc, p.num_comments, p.link_flair_text, p.domain, \
+             p.over_18, p.stickied, p.upvote_ratio",
+            source = SUBREDDIT_SOURCE)
+}
+
+fn post_from_row(row: &Row) -> RawPostFeatures {
+    RawPostFeatures {
+        id: row.get(0),
+        name: row.get(1),
+        is_self: row.get(2),
+        author: row.get(3),
+        url: row.get(4),
+        downs: row.get::<_, i64>(5) as u64,
+        ups: row.get::<_, i64>(6) as u64,
+        score: row.get::<_, i64>(7) as u64,
+        selftext: row.get(8),
+        subreddit: row.get(9),
+        title: row.get(10),
+        permalink: row.get(11),
+        created_utc: row.get(12),
+        num_comments: row.get::<_, i64>(13) as u64,
+        link_flair_text: row.get(14),
+        domain: row.get(15),
+        over_18: row.get(16),
+        stickied: row.get(17),
+        upvote_ratio: row.get(18),
+    }
+}
+
+/// Posts scraped before ids were recorded get a stable id derived from their content
+fn legacy_id(post: &RawPostFeatures) -> String {
+    let mut hasher = FnvHasher::default();
+    post.author.hash(&mut hasher);
+    post.title.hash(&mut hasher);
+    post.selftext.hash(&mut hasher);
+    format!("legacy_{:016x}", hasher.finish())
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Raw posts, their labels and named snapshots of the post set, stored in a SQLite file
+pub struct Dataset {
+    conn: Connection,
+}
+
+impl Dataset {
+    /// Opens the dataset at `path`, creating the file and its tables if needed
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Dataset> {
+        Dataset::from_connection(try!(Connection::open(path)))
+    }
+
+    pub fn open_in_memory() -> Result<Dataset> {
+        Dataset::from_connection(try!(Connection::open_in_memory()))
+    }
+
+    fn from_connection(conn: Connection) -> Result<Dataset> {
+        // SQLite leaves `REFERENCES` unchecked unless asked to, per connection
+        try!(conn.execute_batch("PRAGMA foreign_keys = ON"));
+        try!(conn.execute_batch(SCHEMA));
+        Ok(Dataset { conn: conn })
+    }
+
+    /// Inserts `post` unless a post with its id is already stored, and labels it with its
+    /// subreddit. Returns whether it was inserted.
+    pub fn insert_post(&mut self, post: &RawPostFeatures) -> Result<bool> {
+        let id = if post.id.is_empty() {
+            legacy_id(post)
+        } else {
+            post.id.clone()
+        };
+
+        let inserted = try!(self.conn.execute("INSERT OR IGNORE INTO posts VALUES (?, ?, ?, ?, \
+                                               ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                                              &[&id,
+                                                &post.name,
+                                                &post.is_self,
+                                                &post.author,
+                                                &post.url,
+                                                &(post.downs as i64),
+                                                &(post.ups as i64),
+                                                &(post.score as i64),
+                                                &post.selftext,
+                                                &post.subreddit,
+                                                &post.title,
+                                                &post.permalink,
+                                                &post.created_utc,
+                                                &(post.num_comments as i64),
+                                                &post.link_flair_text,
+                                                &post.domain,
+                                                &post.over_18,
+                                                &post.stickied,
+                                                &post.upvote_ratio]));
+
+        if inserted > 0 {
+            try!(self.label(&id, &post.subreddit, SUBREDDIT_SOURCE));
+        }
+        Ok(inserted > 0)
+    }
+
+    /// Records `label` for a post, replacing any earlier label from the same `source`
+    pub fn label(&mut self, post_id: &str, label: &str, source: &str) -> Result<()> {
+        try!(self.conn.execute("INSERT OR REPLACE INTO labels VALUES (?, ?, ?, ?)",
+                               &[&post_id, &label, &source, &now()]));
+        Ok(())
+    }
+
+    /// Inserts every post in a single transaction, returning how many were new
+    pub fn import_posts<'a, I>(&mut self, posts: I) -> Result<usize>
+        where I: IntoIterator<Item = &'a RawPostFeatures>
+    {
+        try!(self.conn.execute_batch("BEGIN"));
+        let mut inserted = 0;
+        for post in posts {
+            match self.insert_post(post) {
+                Ok(true) => inserted += 1,
+                Ok(false) => (),
+                Err(e) => {
+                    let _ = self.conn.execute_batch("ROLLBACK");
+                    return Err(e);
+                }
+            }
+        }
+        try!(self.conn.execute_batch("COMMIT"));
+        Ok(inserted)
+    }
+
+    /// Imports a CSV of posts in either the current or the legacy column layout
+    pub fn import_csv(&mut self, path: &str) -> Result<usize> {
+        let posts = try!(read_posts(path));
+        self.import_posts(&posts)
+    }
+
+    pub fn import_store(&mut self, store: &PostStore) -> Result<usize> {
+        self.import_posts(store.posts())
+    }
+
+    /// Tags every post currently in the dataset with `tag`, so later training runs can be
+    /// repeated on exactly the same posts. Returns the number of posts tagged.
+    pub fn tag_snapshot(&mut self, tag: &str) -> Result<usize> {
+        let tagged = try!(self.conn.execute("INSERT OR IGNORE INTO snapshots SELECT ?, id FROM \
+                                             posts",
+                                            &[&tag]));
+        Ok(tagged as usize)
+    }
+
+    fn query_posts(&self, select: &str, snapshot: Option<&str>) -> Result<Vec<RawPostFeatures>> {
+        let posts = match snapshot {
+            Some(tag) => {
+                let query = format!("SELECT {} FROM posts p JOIN snapshots s ON s.post_id = \
+                                     p.id WHERE s.tag = ? ORDER BY p.created_utc",
+                                    select);
+                let mut stmt = try!(self.conn.prepare(&query));
+                let rows = try!(stmt.query_map(&[&tag], post_from_row));
+                let posts = try!(rows.collect::<rusqlite::Result<Vec<_>>>());
+                posts
+            }
+            None => {
+                let query = format!("SELECT {} FROM posts p ORDER BY p.created_utc", select);
+                let mut stmt = try!(self.conn.prepare(&query));
+                let rows = try!(stmt.query_map(&[], post_from_row));
+                let posts = try!(rows.collect::<rusqlite::Result<Vec<_>>>());
+                posts
+            }
+        };
+        Ok(posts)
+    }
+
+    /// Every post, or only those in `snapshot`, oldest first
+    pub fn posts(&self, snapshot: Option<&str>) -> Result<Vec<RawPostFeatures>> {
+        self.query_posts(POST_COLUMNS, snapshot)
+    }
+
+    /// Like `posts`, with every post's `subreddit` replaced by its resolved label, ready to be
+    /// fit on
+    pub fn labeled_posts(&self, snapshot: Option<&str>) -> Result<Vec<RawPostFeatures>> {
+        self.query_posts(&labeled_post_columns(), snapshot)
+    }
+
+    /// Writes every post, or only those in `snapshot`, to a CSV readable by `read_posts`.
+    /// Returns the number of posts written.
+    pub fn export_csv(&self, path: &str, snapshot: Option<&str>) -> Result<usize> {
+        let posts = try!(self.posts(snapshot));
+        let mut wtr = try!(Writer::from_file(path));
+        for post in &posts {
+            try!(wtr.encode(post));
+        }
+        Ok(posts.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reddit::RawPostFeatures;
+    use util::temp_dir;
+
+    use std::fs;
+
+    fn post(id: &str, subreddit: &str, created_utc: f64) -> RawPostFeatures {
+        RawPostFeatures {
+            id: id.to_owned(),
+            name: format!("t3_{}", id),
+            subreddit: subreddit.to_owned(),
+            title: format!("title {}", id),
+            created_utc: created_utc,
+            link_flair_text: Some("help".to_owned()),
+            ..RawPostFeatures::default()
+        }
+    }
+
+    #[test]
+    fn test_insert_and_read_back() {
+        let mut dataset = Dataset::open_in_memory().unwrap();
+        let posts = vec![post("b", "rust", 2.0),
+                         post("a", "playrust", 1.0),
+                         post("a", "rust", 1.0)];
+
+        assert_eq!(dataset.import_posts(&posts).unwrap(), 2);
+
+        let stored = dataset.posts(None).unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[0].id, "a");
+        assert_eq!(stored[0].subreddit, "playrust");
+        assert_eq!(stored[0].link_flair_text, Some("help".to_owned()));
+    }
+
+    #[test]
+    fn test_manual_label_overrides_subreddit() {
+        let mut dataset = Dataset::open_in_memory().unwrap();
+        dataset.import_posts(&[post("a", "rust", 1.0), post("b", "rust", 2.0)]).unwrap();
+        dataset.label("a", "playrust", "manual").unwrap();
+
+        let labeled = dataset.labeled_posts(None).unwrap();
+        assert_eq!(labeled[0].subreddit, "playrust");
+        assert_eq!(labeled[1].subreddit, "rust");
+    }
+
+    #[test]
+    fn test_label_requires_stored_post() {
+        let mut dataset = Dataset::open_in_memory().unwrap();
+        dataset.import_posts(&[post("a", "rust", 1.0)]).unwrap();
+
+        assert!(dataset.label("a", "playrust", "manual").is_ok());
+        assert!(dataset.label("missing", "playrust", "manual").is_err());
+    }
+
+    #[test]
+    fn test_snapshots() {
+        let mut dataset = Dataset::open_in_memory().unwrap();
+        dataset.import_posts(&[post("a", "rust", 1.0)]).unwrap();
+        assert_eq!(dataset.tag_snapshot("v1").unwrap(), 1);
+        dataset.import_posts(&[post("b", "rust", 2.0)]).unwrap();
+
+        assert_eq!(dataset.posts(Some("v1")).unwrap().len(), 1);
+        assert_eq!(dataset.posts(None).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let dir = temp_dir("dataset_csv");
+        fs::create_dir_all(&dir).unwrap();
+        let csv_path = dir.join("posts.csv");
+        let csv_path = csv_path.to_str().unwrap();
+
+        let mut dataset = Dataset::open_in_memory().unwrap();
+        dataset.import_posts(&[post("a", "rust", 1.0), post("b", "playrust", 2.0)]).unwrap();
+        assert_eq!(dataset.export_csv(csv_path, None).unwrap(), 2);
+
+        let mut imported = Dataset::open(dir.join("dataset.sqlite")).unwrap();
+        imported.import_csv(csv_path).unwrap();
+        let posts = imported.posts(None).unwrap();
+        assert_eq!(posts.iter().map(|p| p.id.as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+    }
+}
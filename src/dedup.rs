@@ -0,0 +1,358 @@
+use fnv::FnvHasher;
+
+use error::{Error, Result};
+use feature_extraction::get_words;
+use reddit::RawPostFeatures;
+
+use std::cmp;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+
+/// How near-duplicate posts are detected
+#[derive(Debug, Clone)]
+pub struct DedupConfig {
+    /// Number of consecutive words hashed into one shingle
+    pub shingle_size: usize,
+    /// Length of the MinHash signatures
+    pub num_hashes: usize,
+    /// Number of LSH bands the signatures are split into, must divide `num_hashes`. More bands
+    /// find pairs with a lower similarity at the cost of more candidate pairs to verify.
+    pub bands: usize,
+    /// Posts whose shingle sets have at least this Jaccard similarity are duplicates
+    pub threshold: f32,
+}
+
+impl Default for DedupConfig {
+    fn default() -> DedupConfig {
+        DedupConfig {
+            shingle_size: 3,
+            num_hashes: 128,
+            bands: 32,
+            threshold: 0.8,
+        }
+    }
+}
+
+fn hash<T: Hash>(value: &T) -> u64 {
+    let mut hasher = FnvHasher::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The splitmix64 finalizer, used to derive the MinHash permutations from a single hash
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// Hashes every window of `size` consecutive words. Texts shorter than a window form a single
+/// shingle. The result is sorted and free of duplicates.
+pub fn shingles(words: &[String], size: usize) -> Vec<u64> {
+    let mut shingles: Vec<u64> = if words.is_empty() {
+        vec![]
+    } else if words.len() <= size {
+        vec![hash(&words)]
+    } else {
+        words.windows(size).map(|window| hash(&window)).collect()
+    };
+    shingles.sort();
+    shingles.dedup();
+    shingles
+}
+
+/// The shingles of a post's title and selftext
+pub fn post_shingles(post: &RawPostFeatures, size: usize) -> Vec<u64> {
+    let words = get_words(&format!("{} {}", post.title, post.selftext));
+    shingles(&words, size)
+}
+
+/// The MinHash signature of a shingle set. The fraction of equal positions in two signatures
+/// estimates the Jaccard similarity of their sets.
+pub fn signature(shingles: &[u64], num_hashes: usize) -> Vec<u64> {
+    (0..num_hashes)
+        .map(|i| {
+            let seed = mix(i as u64 + 1);
+            shingles.iter().map(|&s| mix(s ^ seed)).min().unwrap_or(u64::max_value())
+        })
+        .collect()
+}
+
+/// The Jaccard similarity of two sorted shingle sets
+pub fn jaccard(a: &[u64], b: &[u64]) -> f32 {
+    if a.is_empty() && b.is_empty() {
+        return 0.0;
+    }
+
+    let (mut i, mut j, mut shared) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            shared += 1;
+            i += 1;
+            j += 1;
+        } else if a[i] < b[j] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    shared as f32 / (a.len() + b.len() - shared) as f32
+}
+
+fn find(parents: &mut Vec<usize>, i: usize) -> usize {
+    let mut root = i;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    let mut i = i;
+    while parents[i] != root {
+        let next = parents[i];
+        parents[i] = root;
+        i = next;
+    }
+    root
+}
+
+fn union(parents: &mut Vec<usize>, a: usize, b: usize) {
+    let (a, b) = (find(parents, a), find(parents, b));
+    if a != b {
+        parents[cmp::max(a, b)] = cmp::min(a, b);
+    }
+}
+
+/// Groups of near-duplicate posts, found by `find_duplicates`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateClusters {
+    cluster_of: Vec<usize>,
+}
+
+impl DuplicateClusters {
+    /// The cluster of the post at `index`. Posts without duplicates are alone in their cluster.
+    pub fn cluster_of(&self, index: usize) -> usize {
+        self.cluster_of[index]
+    }
+
+    /// The cluster of every post, usable as groups for cross validation
+    pub fn assignments(&self) -> &[usize] {
+        &self.cluster_of
+    }
+
+    /// The indices of the posts in every cluster with more than one post
+    pub fn clusters(&self) -> Vec<Vec<usize>> {
+        let count = self.cluster_of.iter().map(|&c| c + 1).max().unwrap_or(0);
+        let mut clusters = vec![vec![]; count];
+        for (index, &cluster) in self.cluster_of.iter().enumerate() {
+            clusters[cluster].push(index);
+        }
+        clusters.into_iter().filter(|c| c.len() > 1).collect()
+    }
+
+    /// The index of the first post of every cluster
+    pub fn representatives(&self) -> Vec<usize> {
+        let mut seen = vec![false; self.cluster_of.len()];
+        self.cluster_of
+            .iter()
+            .enumerate()
+            .filter_map(|(index, &cluster)| {
+                if seen[cluster] {
+                    None
+                } else {
+                    seen[cluster] = true;
+                    Some(index)
+                }
+            })
+            .collect()
+    }
+
+    /// Writes every cluster of duplicates with the id, subreddit and title of its posts
+    pub fn write_report<W: Write>(&self, posts: &[RawPostFeatures], mut w: W) -> io::Result<()> {
+        let clusters = self.clusters();
+        try!(writeln!(w,
+                      "{} clusters of near-duplicates covering {} posts",
+                      clusters.len(),
+                      clusters.iter().map(|c| c.len()).sum::<usize>()));
+
+        for (n, cluster) in clusters.iter().enumerate() {
+            try!(writeln!(w, "\ncluster {} ({} posts)", n, cluster.len()));
+            for &index in cluster {
+                let post = &posts[index];
+                try!(writeln!(w, "  {}\t{}\t{}", post.dedup_key(), post.subreddit, post.title));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Clusters posts with the same id or whose title and selftext are near-duplicates.
+///
+/// Candidate pairs are posts sharing at least one LSH band of their MinHash signatures, each
+/// candidate is then kept only if the exact Jaccard similarity of the shingles reaches the
+/// threshold. Posts without any words are never near-duplicates.
+pub fn find_duplicates(posts: &[RawPostFeatures],
+                       config: &DedupConfig)
+                       -> Result<DuplicateClusters> {
+    if config.bands == 0 || config.num_hashes % config.bands != 0 {
+        return Err(Error::Config(format!("{} LSH bands do not divide {} MinHash values",
+                                         config.bands,
+                                         config.num_hashes)));
+    }
+    let rows = config.num_hashes / config.bands;
+
+    let shingles: Vec<Vec<u64>> = posts.iter()
+                                       .map(|post| post_shingles(post, config.shingle_size))
+                                       .collect();
+    let mut parents: Vec<usize> = (0..posts.len()).collect();
+
+    let mut by_key = HashMap::new();
+    for (index, post) in posts.iter().enumerate() {
+        let first = *by_key.entry(post.dedup_key()).or_insert(index);
+        union(&mut parents, first, index);
+    }
+
+    let mut buckets: HashMap<(usize, u64), Vec<usize>> = HashMap::new();
+    for (index, set) in shingles.iter().enumerate() {
+        if set.is_empty() {
+            continue;
+        }
+        let minhashes = signature(set, config.num_hashes);
+        for (band, band_rows) in minhashes.chunks(rows).enumerate() {
+            buckets.entry((band, hash(&band_rows))).or_insert_with(Vec::new).push(index);
+        }
+    }
+
+    for bucket in buckets.values().filter(|b| b.len() > 1) {
+        for (n, &a) in bucket.iter().enumerate() {
+            for &b in &bucket[n + 1..] {
+                if find(&mut parents, a) != find(&mut parents, b) &&
+                   jaccard(&shingles[a], &shingles[b]) >= config.threshold {
+                    union(&mut parents, a, b);
+                }
+            }
+        }
+    }
+
+    // Number the clusters in order of their first post
+    let mut ids = HashMap::new();
+    let cluster_of = (0..posts.len())
+                         .map(|index| {
+                             let root = find(&mut parents, index);
+                             let next = ids.len();
+                             *ids.entry(root).or_insert(next)
+                         })
+                         .collect();
+
+    Ok(DuplicateClusters { cluster_of: cluster_of })
+}
+
+/// Keeps only the first post of every cluster of duplicates in `clusters`, which must have been
+/// found for `posts`
+pub fn dedup_posts(posts: Vec<RawPostFeatures>,
+                   clusters: &DuplicateClusters)
+                   -> Vec<RawPostFeatures> {
+    let mut keep = vec![false; posts.len()];
+    for index in clusters.representatives() {
+        keep[index] = true;
+    }
+
+    posts.into_iter()
+         .zip(keep)
+         .filter(|&(_, keep)| keep)
+         .map(|(post, _)| post)
+         .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::hash;
+    use reddit::RawPostFeatures;
+
+    fn words(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_shingles() {
+        let s = shingles(&words("a b c d a b c"), 3);
+        // "a b c" appears twice
+        assert_eq!(s.len(), 4);
+        assert_eq!(shingles(&words("a b"), 3), vec![hash(&words("a b"))]);
+        assert!(shingles(&[], 3).is_empty());
+    }
+
+    #[test]
+    fn test_signature_estimates_jaccard() {
+        let a = shingles(&words("one two three four five six seven eight nine ten"), 1);
+        let b = shingles(&words("one two three four five six seven eight nine eleven"), 1);
+        let expected = jaccard(&a, &b);
+        assert!((expected - 9.0 / 11.0).abs() < 1e-6);
+
+        let (sig_a, sig_b) = (signature(&a, 512), signature(&b, 512));
+        let equal = sig_a.iter().zip(&sig_b).filter(|&(x, y)| x == y).count();
+        let estimate = equal as f32 / 512.0;
+        assert!((estimate - expected).abs() < 0.1,
+                "estimate {} expected {}",
+                estimate,
+                expected);
+    }
+
+    #[test]
+    fn test_same_id_is_duplicate() {
+        let post = |id: &str, title: &str| {
+            RawPostFeatures {
+                id: id.to_owned(),
+                title: title.to_owned(),
+                ..RawPostFeatures::default()
+            }
+        };
+        let posts = vec![post("a", "x"), post("b", "y"), post("a", "z")];
+
+        let clusters = find_duplicates(&posts, &DedupConfig::default()).unwrap();
+        assert_eq!(clusters.clusters(), vec![vec![0, 2]]);
+        assert_eq!(clusters.assignments(), &[0, 1, 0]);
+        assert_eq!(dedup_posts(posts, &clusters).len(), 2);
+    }
+
+    #[test]
+    fn test_near_duplicates_are_clustered() {
+        let post = |id: &str, title: &str, selftext: &str| {
+            RawPostFeatures {
+                id: id.to_owned(),
+                title: title.to_owned(),
+                selftext: selftext.to_owned(),
+                ..RawPostFeatures::default()
+            }
+        };
+        let question = "I am writing a linked list to learn Rust and the compiler keeps telling \
+                        me that I cannot borrow the node as mutable more than once at a time. I \
+                        tried wrapping the next pointer in an Option Box and calling take \
+                        before reassigning the head, but the error moves to the loop that walks \
+                        the list. Is there an idiomatic way to append at the tail without \
+                        unsafe code or reference counting?";
+        let posts = vec![post("a",
+                              "Borrow checker rejects my linked list implementation",
+                              question),
+                         post("b",
+                              "Borrow checker rejects my doubly linked list implementation",
+                              question),
+                         post("c",
+                              "Offline raid on my base",
+                              "Got offline raided overnight, they blew through two metal doors \
+                               with rockets and took everything from the tool cupboard. Any tips \
+                               for honeycombing a small base on a high population server?")];
+
+        let clusters = find_duplicates(&posts, &DedupConfig::default()).unwrap();
+        assert_eq!(clusters.clusters(), vec![vec![0, 1]]);
+        assert_eq!(clusters.assignments(), &[0, 0, 1]);
+    }
+
+    #[test]
+    fn test_bands_must_divide_signature() {
+        let posts = vec![RawPostFeatures::default()];
+        for &bands in &[0, 3] {
+            let config = DedupConfig { bands: bands, ..DedupConfig::default() };
+            assert!(find_duplicates(&posts, &config).is_err());
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use bincode::rustc_serialize::{DecodingError, EncodingError};
+use csv;
+use hyper;
+use rusqlite;
+use serde_json;
+
+use model::ModelError;
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The request could not be sent or its response could not be read
+    Http(hyper::Error),
+    /// The server answered with a non success status code
+    Status(u16),
+    Json(serde_json::Error),
+    /// Reddit refused to grant an OAuth token
+    Auth(String),
+    /// Reddit accepted the request but reported errors in its response
+    Reddit(String),
+    /// A response was missing a field we rely on, or it had an unexpected type
+    MissingField(&'static str),
+    Csv(csv::Error),
+    Io(io::Error),
+    Sql(rusqlite::Error),
+    Decode(DecodingError),
+    Encode(EncodingError),
+    Model(ModelError),
+    /// An error reported by rustlearn while fitting or applying a model
+    Learner(&'static str),
+    /// A deployment setting is missing or invalid
+    Config(String),
+    /// A mail server rejected a command, with its reply
+    Smtp(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Http(ref e) => write!(f, "http error: {}", e),
+            Error::Status(code) => write!(f, "server responded with status {}", code),
+            Error::Json(ref e) => write!(f, "json error: {}", e),
+            Error::Auth(ref e) => write!(f, "authentication failed: {}", e),
+            Error::Reddit(ref e) => write!(f, "reddit rejected the request: {}", e),
+            Error::MissingField(field) => write!(f, "missing or malformed field `{}`", field),
+            Error::Csv(ref e) => write!(f, "csv error: {}", e),
+            Error::Io(ref e) => write!(f, "io error: {}", e),
+            Error::Sql(ref e) => write!(f, "database error: {}", e),
+            Error::Decode(ref e) => write!(f, "decoding error: {}", e),
+            Error::Encode(ref e) => write!(f, "encoding error: {}", e),
+            Error::Model(ref e) => write!(f, "{}", e),
+            Error::Learner(e) => write!(f, "model error: {}", e),
+            Error::Config(ref e) => write!(f, "invalid configuration: {}", e),
+            Error::Smtp(ref e) => write!(f, "mail server error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Http(ref e) => e.description(),
+            Error::Status(_) => "unsuccessful http status",
+            Error::Json(ref e) => e.description(),
+            Error::Auth(_) => "authentication failed",
+            Error::Reddit(_) => "reddit rejected the request",
+            Error::MissingField(_) => "missing or malformed field",
+            Error::Csv(ref e) => e.description(),
+            Error::Io(ref e) => e.description(),
+            Error::Sql(ref e) => e.description(),
+            Error::Decode(ref e) => e.description(),
+            Error::Encode(ref e) => e.description(),
+            Error::Model(ref e) => e.description(),
+            Error::Learner(e) => e,
+            Error::Config(_) => "invalid configuration",
+            Error::Smtp(_) => "mail server error",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Http(ref e) => Some(e),
+            Error::Json(ref e) => Some(e),
+            Error::Csv(ref e) => Some(e),
+            Error::Io(ref e) => Some(e),
+            Error::Sql(ref e) => Some(e),
+            Error::Decode(ref e) => Some(e),
+            Error::Encode(ref e) => Some(e),
+            Error::Model(ref e) => Some(e),
+            Error::Status(_) |
+            Error::Auth(_) |
+            Error::Reddit(_) |
+            Error::MissingField(_) |
+            Error::Learner(_) |
+            Error::Config(_) |
+            Error::Smtp(_) => None,
+        }
+    }
+}
+
+impl From<hyper::Error> for Error {
+    fn from(e: hyper::Error) -> Error {
+        Error::Http(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Error {
+        Error::Json(e)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(e: csv::Error) -> Error {
+        Error::Csv(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Error {
+        Error::Sql(e)
+    }
+}
+
+impl From<DecodingError> for Error {
+    fn from(e: DecodingError) -> Error {
+        Error::Decode(e)
+    }
+}
+
+impl From<EncodingError> for Error {
+    fn from(e: EncodingError) -> Error {
+        Error::Encode(e)
+    }
+}
+
+impl From<ModelError> for Error {
+    fn from(e: ModelError) -> Error {
+        Error::Model(e)
+    }
+}
@@ -0,0 +1,572 @@
+use rustlearn::prelude::*;
+use serde_json;
+
+use cross_validation::FoldOutcome;
+use error::Result;
+
+use std::cmp::{self, Ordering};
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Probabilities are clipped to `[EPSILON, 1 - EPSILON]` so the log-loss stays finite
+const EPSILON: f32 = 1e-7;
+
+/// Metrics of one label within a fold, treating it as the positive class
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ClassMetrics {
+    pub label: String,
+    pub precision: f32,
+    pub recall: f32,
+    pub f1: f32,
+    /// `None` when the fold lacks either positive or negative posts
+    pub roc_auc: Option<f32>,
+    /// Average precision, `None` when the fold has no positive posts
+    pub pr_auc: Option<f32>,
+    /// Number of posts with this label
+    pub support: usize,
+}
+
+/// Metrics of a single fold
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FoldMetrics {
+    /// Label names, in the order of the confusion matrix rows and columns
+    pub labels: Vec<String>,
+    pub accuracy: f32,
+    pub log_loss: f32,
+    pub classes: Vec<ClassMetrics>,
+    /// `confusion[truth][predicted]` counts the posts of label `truth` predicted as `predicted`
+    pub confusion: Vec<Vec<usize>>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub mean: f32,
+    pub stddev: f32,
+    /// Number of folds the metric was defined for
+    pub folds: usize,
+}
+
+impl Summary {
+    pub fn of(values: &[f32]) -> Summary {
+        if values.is_empty() {
+            return Summary {
+                mean: 0.0,
+                stddev: 0.0,
+                folds: 0,
+            };
+        }
+
+        let n = values.len() as f32;
+        let mean = values.iter().sum::<f32>() / n;
+        let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / n;
+        Summary {
+            mean: mean,
+            stddev: variance.sqrt(),
+            folds: values.len(),
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.folds == 0 {
+            write!(f, "{:>15}", "n/a")
+        } else {
+            write!(f, "{:>7.3} ± {:<5.3}", self.mean, self.stddev)
+        }
+    }
+}
+
+/// Per label metrics aggregated across folds
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ClassSummary {
+    pub label: String,
+    pub precision: Summary,
+    pub recall: Summary,
+    pub f1: Summary,
+    pub roc_auc: Summary,
+    pub pr_auc: Summary,
+    /// Number of posts with this label across all folds
+    pub support: usize,
+}
+
+/// Predictions with a confidence in `[lower, upper)`, and how often they were right
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CalibrationBucket {
+    pub lower: f32,
+    pub upper: f32,
+    pub count: usize,
+    pub mean_confidence: f32,
+    pub accuracy: f32,
+}
+
+/// Cross validation results aggregated across folds
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct EvaluationReport {
+    /// Seconds since the unix epoch at which the report was created
+    pub generated_at: u64,
+    /// Label names, in the order of the confusion matrix rows and columns
+    pub labels: Vec<String>,
+    pub accuracy: Summary,
+    pub log_loss: Summary,
+    pub classes: Vec<ClassSummary>,
+    /// The confusion matrices of all folds, summed
+    pub confusion: Vec<Vec<usize>>,
+    /// Calibration of the predicted label's probability, over the posts of all folds
+    pub calibration: Vec<CalibrationBucket>,
+    pub folds: Vec<FoldMetrics>,
+}
+
+/// Turns a row of per label scores into probabilities summing to one
+pub fn normalize_scores(scores: &[f32]) -> Vec<f32> {
+    let total: f32 = scores.iter().map(|&s| s.max(0.0)).sum();
+    if total > 0.0 {
+        scores.iter().map(|&s| s.max(0.0) / total).collect()
+    } else {
+        vec![1.0 / scores.len() as f32; scores.len()]
+    }
+}
+
+fn probabilities(outcome: &FoldOutcome) -> Vec<Vec<f32>> {
+    (0..outcome.scores.rows())
+        .map(|row| {
+            let scores: Vec<f32> = (0..outcome.scores.cols())
+                                       .map(|col| outcome.scores.get(row, col))
+                                       .collect();
+            normalize_scores(&scores)
+        })
+        .collect()
+}
+
+fn argmax(values: &[f32]) -> usize {
+    (0..values.len()).fold(0, |best, i| if values[i] > values[best] { i } else { best })
+}
+
+fn by_score_descending(a: &(f32, bool), b: &(f32, bool)) -> Ordering {
+    b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal)
+}
+
+/// The area under the ROC curve of `(score, is_positive)` pairs, computed as the Mann-Whitney U
+/// statistic with ties counted as half
+pub fn roc_auc(scored: &[(f32, bool)]) -> Option<f32> {
+    let positives = scored.iter().filter(|s| s.1).count();
+    let negatives = scored.len() - positives;
+    if positives == 0 || negatives == 0 {
+        return None;
+    }
+
+    let mut sorted = scored.to_vec();
+    sorted.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+    // Sum the ranks of the positives, giving tied scores their average rank
+    let mut rank_sum = 0.0;
+    let mut start = 0;
+    while start < sorted.len() {
+        let mut end = start;
+        while end + 1 < sorted.len() && sorted[end + 1].0 == sorted[start].0 {
+            end += 1;
+        }
+        let rank = (start + end) as f64 / 2.0 + 1.0;
+        rank_sum += rank * sorted[start..end + 1].iter().filter(|s| s.1).count() as f64;
+        start = end + 1;
+    }
+
+    let (p, n) = (positives as f64, negatives as f64);
+    Some(((rank_sum - p * (p + 1.0) / 2.0) / (p * n)) as f32)
+}
+
+/// The average precision of `(score, is_positive)` pairs, an estimate of the area under the
+/// precision-recall curve
+pub fn pr_auc(scored: &[(f32, bool)]) -> Option<f32> {
+    let positives = scored.iter().filter(|s| s.1).count();
+    if positives == 0 {
+        return None;
+    }
+
+    let mut sorted = scored.to_vec();
+    sorted.sort_by(by_score_descending);
+
+    let mut hits = 0;
+    let mut precision_sum = 0.0;
+    for (n, &(_, positive)) in sorted.iter().enumerate() {
+        if positive {
+            hits += 1;
+            precision_sum += hits as f32 / (n + 1) as f32;
+        }
+    }
+    Some(precision_sum / positives as f32)
+}
+
+impl FoldMetrics {
+    pub fn from_outcome(outcome: &FoldOutcome) -> FoldMetrics {
+        let labels = outcome.labels.clone();
+        let probabilities = probabilities(outcome);
+        let truth: Vec<usize> = outcome.truth.iter().map(|&t| t as usize).collect();
+        let predicted: Vec<usize> = probabilities.iter().map(|p| argmax(p)).collect();
+
+        let mut confusion = vec![vec![0; labels.len()]; labels.len()];
+        for (&t, &p) in truth.iter().zip(&predicted) {
+            confusion[t][p] += 1;
+        }
+
+        let correct = (0..labels.len()).map(|l| confusion[l][l]).sum::<usize>();
+        let accuracy = if truth.is_empty() {
+            0.0
+        } else {
+            correct as f32 / truth.len() as f32
+        };
+
+        let log_loss = if truth.is_empty() {
+            0.0
+        } else {
+            -probabilities.iter()
+                          .zip(&truth)
+                          .map(|(p, &t)| p[t].max(EPSILON).min(1.0 - EPSILON).ln())
+                          .sum::<f32>() / truth.len() as f32
+        };
+
+        let classes = labels.iter()
+                            .enumerate()
+                            .map(|(l, label)| {
+                                let true_positives = confusion[l][l] as f32;
+                                let predicted_count = (0..labels.len())
+                                                          .map(|t| confusion[t][l])
+                                                          .sum::<usize>();
+                                let support = confusion[l].iter().sum::<usize>();
+
+                                let precision = if predicted_count > 0 {
+                                    true_positives / predicted_count as f32
+                                } else {
+                                    0.0
+                                };
+                                let recall = if support > 0 {
+                                    true_positives / support as f32
+                                } else {
+                                    0.0
+                                };
+                                let f1 = if precision + recall > 0.0 {
+                                    2.0 * precision * recall / (precision + recall)
+                                } else {
+                                    0.0
+                                };
+
+                                let scored: Vec<(f32, bool)> = probabilities.iter()
+                                                                            .zip(&truth)
+                                                                            .map(|(p, &t)| {
+                                                                                (p[l], t == l)
+                                                                            })
+                                                                            .collect();
+
+                                ClassMetrics {
+                                    label: label.clone(),
+                                    precision: precision,
+                                    recall: recall,
+                                    f1: f1,
+                                    roc_auc: roc_auc(&scored),
+                                    pr_auc: pr_auc(&scored),
+                                    support: support,
+                                }
+                            })
+                            .collect();
+
+        FoldMetrics {
+            labels: labels,
+            accuracy: accuracy,
+            log_loss: log_loss,
+            classes: classes,
+            confusion: confusion,
+        }
+    }
+}
+
+/// Buckets the probability of every predicted label into `buckets` equal width buckets
+fn calibration(outcomes: &[FoldOutcome], buckets: usize) -> Vec<CalibrationBucket> {
+    let mut counts = vec![0; buckets];
+    let mut confidence = vec![0.0; buckets];
+    let mut correct = vec![0; buckets];
+
+    for outcome in outcomes {
+        for (p, &t) in probabilities(outcome).iter().zip(&outcome.truth) {
+            let predicted = argmax(p);
+            let bucket = cmp::min((p[predicted] * buckets as f32) as usize, buckets - 1);
+            counts[bucket] += 1;
+            confidence[bucket] += p[predicted];
+            if predicted == t as usize {
+                correct[bucket] += 1;
+            }
+        }
+    }
+
+    (0..buckets)
+        .map(|b| {
+            let count = counts[b];
+            CalibrationBucket {
+                lower: b as f32 / buckets as f32,
+                upper: (b + 1) as f32 / buckets as f32,
+                count: count,
+                mean_confidence: if count > 0 {
+                    confidence[b] / count as f32
+                } else {
+                    0.0
+                },
+                accuracy: if count > 0 {
+                    correct[b] as f32 / count as f32
+                } else {
+                    0.0
+                },
+            }
+        })
+        .collect()
+}
+
+/// Summarizes a metric over the folds it is defined for
+fn summarize<F>(metrics: &[&ClassMetrics], metric: F) -> Summary
+    where F: Fn(&ClassMetrics) -> Option<f32>
+{
+    let values: Vec<f32> = metrics.iter().filter_map(|c| metric(c)).collect();
+    Summary::of(&values)
+}
+
+impl EvaluationReport {
+    /// Evaluates every fold and aggregates the results. Folds may order their labels
+    /// differently, they are matched by name.
+    pub fn from_outcomes(outcomes: &[FoldOutcome], calibration_buckets: usize) -> EvaluationReport {
+        let folds: Vec<FoldMetrics> = outcomes.iter().map(FoldMetrics::from_outcome).collect();
+
+        let mut labels: Vec<String> = Vec::new();
+        for fold in &folds {
+            for label in &fold.labels {
+                if !labels.contains(label) {
+                    labels.push(label.clone());
+                }
+            }
+        }
+        let index_of = |label: &String| labels.iter().position(|l| l == label).unwrap();
+
+        let mut confusion = vec![vec![0; labels.len()]; labels.len()];
+        for fold in &folds {
+            for (t, row) in fold.confusion.iter().enumerate() {
+                for (p, &count) in row.iter().enumerate() {
+                    confusion[index_of(&fold.labels[t])][index_of(&fold.labels[p])] += count;
+                }
+            }
+        }
+
+        let classes = labels.iter()
+                            .map(|label| {
+                                let metrics: Vec<&ClassMetrics> =
+                                    folds.iter()
+                                         .flat_map(|f| f.classes.iter())
+                                         .filter(|c| c.label == *label)
+                                         .collect();
+                                ClassSummary {
+                                    label: label.clone(),
+                                    precision: summarize(&metrics, |c| Some(c.precision)),
+                                    recall: summarize(&metrics, |c| Some(c.recall)),
+                                    f1: summarize(&metrics, |c| Some(c.f1)),
+                                    roc_auc: summarize(&metrics, |c| c.roc_auc),
+                                    pr_auc: summarize(&metrics, |c| c.pr_auc),
+                                    support: metrics.iter().map(|c| c.support).sum(),
+                                }
+                            })
+                            .collect();
+
+        let accuracy: Vec<f32> = folds.iter().map(|f| f.accuracy).collect();
+        let log_loss: Vec<f32> = folds.iter().map(|f| f.log_loss).collect();
+
+        let generated_at = SystemTime::now()
+                               .duration_since(UNIX_EPOCH)
+                               .map(|d| d.as_secs())
+                               .unwrap_or(0);
+
+        EvaluationReport {
+            generated_at: generated_at,
+            labels: labels.clone(),
+            accuracy: Summary::of(&accuracy),
+            log_loss: Summary::of(&log_loss),
+            classes: classes,
+            confusion: confusion,
+            calibration: calibration(outcomes, calibration_buckets),
+            folds: folds,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(try!(serde_json::to_string(self)))
+    }
+}
+
+/// The lowest probability at which predictions of each of `labels` still reach
+/// `target_precision` across the folds, keeping as many predictions as possible.
+///
+/// A post predicted as a label with a probability below its threshold should be abstained on.
+/// Labels that never reach the target precision get a threshold of 1, so only unanimous
+/// predictions of them are kept.
+pub fn tune_thresholds(outcomes: &[FoldOutcome],
+                       labels: &[String],
+                       target_precision: f32)
+                       -> Vec<f32> {
+    labels.iter()
+          .map(|label| {
+              // The probability and correctness of every prediction of `label`
+              let mut predictions: Vec<(f32, bool)> = Vec::new();
+              for outcome in outcomes {
+                  let l = match outcome.labels.iter().position(|o| o == label) {
+                      Some(l) => l,
+                      None => continue,
+                  };
+                  for (p, &t) in probabilities(outcome).iter().zip(&outcome.truth) {
+                      if argmax(p) == l {
+                          predictions.push((p[l], t as usize == l));
+                      }
+                  }
+              }
+              predictions.sort_by(by_score_descending);
+
+              let mut threshold = 1.0;
+              let mut correct = 0;
+              for (n, &(probability, is_correct)) in predictions.iter().enumerate() {
+                  if is_correct {
+                      correct += 1;
+                  }
+                  // Only cut between distinct probabilities, a threshold keeps all ties
+                  let last_of_tie = predictions.get(n + 1)
+                                               .map_or(true, |next| next.0 < probability);
+                  if last_of_tie && correct as f32 / (n + 1) as f32 >= target_precision {
+                      threshold = probability;
+                  }
+              }
+              threshold
+          })
+          .collect()
+}
+
+impl fmt::Display for EvaluationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f, "{} folds", self.folds.len()));
+        try!(writeln!(f, "accuracy  {}", self.accuracy));
+        try!(writeln!(f, "log-loss  {}", self.log_loss));
+
+        try!(writeln!(f,
+                      "\n{:<12} {:>15} {:>15} {:>15} {:>15} {:>15} {:>8}",
+                      "label",
+                      "precision",
+                      "recall",
+                      "f1",
+                      "roc-auc",
+                      "pr-auc",
+                      "support"));
+        for c in &self.classes {
+            try!(writeln!(f,
+                          "{:<12} {} {} {} {} {} {:>8}",
+                          c.label,
+                          c.precision,
+                          c.recall,
+                          c.f1,
+                          c.roc_auc,
+                          c.pr_auc,
+                          c.support));
+        }
+
+        try!(writeln!(f, "\nconfusion matrix (rows are the true label)"));
+        try!(write!(f, "{:<12}", ""));
+        for label in &self.labels {
+            try!(write!(f, " {:>10}", label));
+        }
+        try!(writeln!(f, ""));
+        for (label, row) in self.labels.iter().zip(&self.confusion) {
+            try!(write!(f, "{:<12}", label));
+            for count in row {
+                try!(write!(f, " {:>10}", count));
+            }
+            try!(writeln!(f, ""));
+        }
+
+        try!(writeln!(f, "\ncalibration"));
+        try!(writeln!(f, "{:<12} {:>8} {:>10} {:>10}", "confidence", "posts", "mean", "accuracy"));
+        for b in self.calibration.iter().filter(|b| b.count > 0) {
+            try!(writeln!(f,
+                          "{:<12} {:>8} {:>10.3} {:>10.3}",
+                          format!("{:.1}-{:.1}", b.lower, b.upper),
+                          b.count,
+                          b.mean_confidence,
+                          b.accuracy));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cross_validation::FoldOutcome;
+
+    use rustlearn::prelude::*;
+
+    fn outcome(truth: Vec<f32>, scores: Vec<f32>) -> FoldOutcome {
+        let mut scores = Array::from(scores);
+        scores.reshape(truth.len(), 2);
+        FoldOutcome {
+            test: (0..truth.len()).collect(),
+            truth: truth,
+            scores: scores,
+            labels: vec!["rust".to_owned(), "playrust".to_owned()],
+        }
+    }
+
+    #[test]
+    fn test_roc_auc() {
+        let perfect = vec![(0.9, true), (0.8, true), (0.3, false), (0.1, false)];
+        assert_eq!(roc_auc(&perfect), Some(1.0));
+
+        let tied = vec![(0.5, true), (0.5, false)];
+        assert_eq!(roc_auc(&tied), Some(0.5));
+
+        assert_eq!(roc_auc(&[(0.5, true)]), None);
+    }
+
+    #[test]
+    fn test_pr_auc() {
+        let scored = vec![(0.9, true), (0.8, false), (0.7, true)];
+        // Precision 1 at the first hit and 2/3 at the second
+        assert!((pr_auc(&scored).unwrap() - (1.0 + 2.0 / 3.0) / 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_fold_metrics() {
+        let fold = outcome(vec![0.0, 0.0, 1.0, 1.0],
+                           vec![0.9, 0.1, 0.6, 0.4, 0.3, 0.7, 0.8, 0.2]);
+        let metrics = FoldMetrics::from_outcome(&fold);
+
+        assert_eq!(metrics.confusion, vec![vec![2, 0], vec![1, 1]]);
+        assert_eq!(metrics.accuracy, 0.75);
+        assert_eq!(metrics.classes[0].precision, 2.0 / 3.0);
+        assert_eq!(metrics.classes[1].recall, 0.5);
+        assert_eq!(metrics.classes[1].support, 2);
+    }
+
+    #[test]
+    fn test_report_matches_labels_by_name() {
+        let a = outcome(vec![0.0, 1.0], vec![1.0, 0.0, 0.0, 1.0]);
+        let mut b = outcome(vec![0.0, 1.0], vec![1.0, 0.0, 0.0, 1.0]);
+        b.labels.reverse();
+
+        let report = EvaluationReport::from_outcomes(&[a, b], 10);
+        assert_eq!(report.confusion, vec![vec![2, 0], vec![0, 2]]);
+        assert_eq!(report.accuracy.mean, 1.0);
+        assert_eq!(report.accuracy.stddev, 0.0);
+        assert_eq!(report.calibration[9].count, 4);
+        assert!(report.to_json().unwrap().contains("\"roc_auc\""));
+    }
+
+    #[test]
+    fn test_tune_thresholds() {
+        // The wrong rust prediction has probability 0.7, only predictions above it are precise
+        let fold = outcome(vec![0.0, 0.0, 1.0, 1.0],
+                           vec![9.0, 1.0, 8.0, 2.0, 7.0, 3.0, 1.0, 9.0]);
+        let labels = vec!["rust".to_owned(), "playrust".to_owned(), "other".to_owned()];
+
+        let thresholds = tune_thresholds(&[fold], &labels, 1.0);
+        assert_eq!(thresholds, vec![0.8, 0.9, 1.0]);
+    }
+}
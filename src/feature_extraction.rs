@@ -1,8 +1,9 @@
+use markdown::{code_line_ratio, extract_code};
 use regex::Regex;
-// use rsml::tfidf_helper::*;
-// use tfidf::{TfIdf, TfIdfDefault};
+use reddit::Comment;
+use rust_lexer::{looks_like_rust, rust_construct_counts, tokenize, RUST_CONSTRUCT_NAMES};
+use tokenizer::Tokenizer;
 use std::collections::{BTreeMap, HashMap};
-use std::ascii::AsciiExt;
 
 use std::hash::BuildHasherDefault;
 use fnv::FnvHasher;
@@ -34,41 +35,12 @@ pub fn convert_author_to_popularity(authors: &[&str], rust_authors: &[&str]) ->
            .collect()
 }
 
-// pub fn text_to_docs<T: AsRef<str>>(texts: &[&str]) -> Vec<Vec<(String, usize)>> {
-//     let mut docs = Vec::with_capacity(texts.len());
-//     texts.par_iter()
-//          .map(|s| str_to_doc(s))
-//          .collect_into(&mut docs);
-//     docs
-// }
-
-// // TODO: This should probably return an ndarray
-// pub fn tfidf_reduce_selftext(self_texts: &[&str],
-//                              words: &[&str],
-//                              docs: &[Vec<(String, usize)>])
-//                              -> Vec<Vec<f32>> {
-//
-//     let docs: Vec<Vec<_>> = docs.iter()
-//                                 .map(|doc| doc.iter().map(|t| (t.0.as_str(), t.1)).collect())
-//                                 .collect();
-//     let all_docs = docs.clone();
-//
-//     let mut term_frequency_matrix = Vec::with_capacity(self_texts.len());
-//     println!("TFIDF over {:?} words and {} docs", words.len(), docs.len());
-//
-//     for doc in docs {
-//         let mut term_frequencies: Vec<f32> = Vec::with_capacity(words.len());
-//
-//         words.par_iter()
-//              .weight_max()
-//              .map(|word| TfIdfDefault::tfidf(word, &doc, all_docs.iter()))
-//              .collect_into(&mut term_frequencies);
-//
-//         term_frequency_matrix.push(term_frequencies);
-//     }
-//
-//     term_frequency_matrix
-// }
+/// The symbols counted by `symbol_counts`, in column order
+pub const SYMBOLS: [char; 26] = ['_', '-', ';', ':', '!', '?', '.', '(', ')', '[', ']', '{', '}',
+                                 '*', '/', '\\', '&', '%', '`', '+', '<', '=', '>', '|', '~', '$'];
+
+/// Names of the regexes matched by `check_for_code`, in column order
+pub const CODE_REGEX_NAMES: [&'static str; 4] = ["fn", "let", "if_let", "macro"];
 
 fn bool_to_f32(b: bool) -> f32 {
     if b {
@@ -78,7 +50,8 @@ fn bool_to_f32(b: bool) -> f32 {
     }
 }
 
-pub fn check_for_code(self_texts: &[&str]) -> Vec<Vec<f32>> {
+/// Matches `text` against every code regex, in `CODE_REGEX_NAMES` order
+fn code_regex_matches(text: &str) -> Vec<f32> {
     lazy_static! {
         static ref FN_REGEX: Regex = Regex::new(r".*fn [:alpha:]{1}[:word:]*\(.*\)").expect("fn_regex");
         static ref LET_REGEX: Regex = Regex::new(r".*let( mut)? [:alpha:]{1}[:word:]*.* = .*;").expect("let_regex");
@@ -86,38 +59,83 @@ pub fn check_for_code(self_texts: &[&str]) -> Vec<Vec<f32>> {
         static ref MACRO_REGEX: Regex = Regex::new(r".*[:alpha:]{1}[:word:]*! {0,1}[\{\(\[].*[\)\]\}]").expect("macro_regex");
     }
 
+    vec![FN_REGEX.is_match(text),
+         LET_REGEX.is_match(text),
+         IF_LET_REGEX.is_match(text),
+         MACRO_REGEX.is_match(text)]
+        .into_iter()
+        .map(|b| bool_to_f32(b))
+        .collect()
+}
+
+pub fn check_for_code(self_texts: &[&str]) -> Vec<Vec<f32>> {
+    self_texts.iter().map(|text| code_regex_matches(text)).collect()
+}
+
+/// Like `check_for_code`, but parses every text as markdown and only looks inside its code
+/// blocks and inline code, so prose like "let me know = ...;" is not mistaken for code
+pub fn check_for_code_in_markdown(self_texts: &[&str]) -> Vec<Vec<f32>> {
+    self_texts.iter().map(|text| code_regex_matches(&extract_code(text).code())).collect()
+}
+
+/// Names of the columns produced by `markdown_code_features`
+pub const MARKDOWN_FEATURE_NAMES: [&'static str; 3] = ["code_blocks",
+                                                       "inline_code",
+                                                       "code_line_ratio"];
+
+/// Counts the code blocks and inline code spans of every markdown text, and the fraction of
+/// its lines inside code blocks
+pub fn markdown_code_features(self_texts: &[&str]) -> Vec<Vec<f32>> {
     self_texts.iter()
               .map(|text| {
-                  vec![FN_REGEX.is_match(text),
-                       LET_REGEX.is_match(text),
-                       IF_LET_REGEX.is_match(text),
-                       MACRO_REGEX.is_match(text)]
-                      .into_iter()
-                      .map(|b| bool_to_f32(b))
-                      .collect()
+                  let regions = extract_code(text);
+                  vec![regions.blocks.len() as f32,
+                       regions.inline.len() as f32,
+                       code_line_ratio(text, &regions)]
               })
               .collect()
 }
 
-fn depluralize(s: &str) -> &str {
-    if s.chars().last().unwrap() == 's' {
-        &s[..s.len() - 1]
-    } else {
-        s
-    }
+/// Lexes the code regions of every markdown text and counts the Rust constructs named by
+/// `RUST_CONSTRUCT_NAMES`. The `parses` column is 1 if any single code block or inline code span
+/// looks like a Rust item or expression.
+pub fn rust_syntax_features(self_texts: &[&str]) -> Vec<Vec<f32>> {
+    self_texts.iter()
+              .map(|text| {
+                  let regions = extract_code(text);
+                  let mut counts = rust_construct_counts(&tokenize(&regions.code()));
+                  let parses = regions.blocks
+                                      .iter()
+                                      .chain(&regions.inline)
+                                      .any(|region| looks_like_rust(&tokenize(region)));
+                  counts[RUST_CONSTRUCT_NAMES.len() - 1] = bool_to_f32(parses);
+                  counts
+              })
+              .collect()
 }
 
-fn should_replace(c: u8) -> bool {
-    match c {
-        97...122 => true,
-        65...90 => true,
-        _ => false,
-    }
+/// Names of the columns produced by `comment_features`
+pub const COMMENT_FEATURE_NAMES: [&'static str; 3] = ["count", "playrust_mentions", "with_code"];
+
+/// Summarizes the replies to a post: how many there are, how many point at /r/playrust and how
+/// many contain rust code. Not a pipeline column yet: neither training data nor inference carries
+/// the comments of a post.
+pub fn comment_features(comments: &[Comment]) -> Vec<f32> {
+    let bodies: Vec<&str> = comments.iter().map(|c| c.body.as_str()).collect();
+
+    let playrust_mentions = bodies.iter()
+                                  .filter(|body| body.to_lowercase().contains("playrust"))
+                                  .count();
+    let with_code = check_for_code(&bodies[..])
+                        .iter()
+                        .filter(|matches| matches.iter().any(|&m| m > 0f32))
+                        .count();
+
+    vec![comments.len() as f32, playrust_mentions as f32, with_code as f32]
 }
 
 pub fn symbol_counts(self_texts: &[&str]) -> Vec<Vec<f32>> {
-    let symbols = ['_', '-', ';', ':', '!', '?', '.', '(', ')', '[', ']', '{', '}', '*', '/',
-                   '\\', '&', '%', '`', '+', '<', '=', '>', '|', '~', '$'];
+    let symbols = SYMBOLS;
 
     let mut freq_matrix = Vec::with_capacity(self_texts.len());
 
@@ -151,79 +169,50 @@ pub fn symbol_counts(self_texts: &[&str]) -> Vec<Vec<f32>> {
 }
 
 
+/// Splits `sentence` into terms with the default `Tokenizer`
 pub fn get_words(sentence: &str) -> Vec<String> {
-    let cleaned = sentence;
-
-    let cleaned: String = cleaned.chars()
-                                 .filter(|c| c.is_ascii())
-                                 .collect();
-
-    let mut cleaned: Vec<u8> = cleaned.bytes().collect();
-
-    for c in cleaned.as_mut_slice() {
-        if should_replace(*c) {
-            *c = b' ';
-        }
-    }
-
-
-    // let cleaned: Vec<_> = cleaned.into_iter()
-    //                              .filter(|c| !should_drop(*c))
-    //                              .collect();
-
-    // println!("{:?}", cleaned.len());
-    // We take in a &str and filter all non-ascii out, this is safe
-    let cleaned = unsafe { String::from_utf8_unchecked(cleaned) };
-    let cleaned = cleaned.to_lowercase();
-
-
-    cleaned.split_whitespace()
-           .filter(|s| 2 < s.len() && s.len() < 10)
-           .map(|s| depluralize(s))
-           .filter(|s| 2 < s.len())
-           .map(String::from)
-           .collect()
+    Tokenizer::default().tokenize(sentence)
 }
 
 
-pub fn interesting_word_freq(self_texts: &[&str], spec_words: &[String]) -> Vec<Vec<f32>> {
+/// Counts occurrences of every word in `spec_words` within each text, after splitting the text
+/// with `tokenizer`. `spec_words` must already be normalized by the same tokenizer, see
+/// `Tokenizer::vocabulary`. The columns of the returned rows follow the order of `spec_words`.
+pub fn interesting_word_freq(self_texts: &[&str],
+                             spec_words: &[String],
+                             tokenizer: &Tokenizer)
+                             -> Vec<Vec<f32>> {
 
     let mut freq_matrix = Vec::with_capacity(self_texts.len());
     let text_words: Vec<Vec<String>> = self_texts.iter()
-                                                 .map(|t| get_words(*t))
+                                                 .map(|t| tokenizer.tokenize(t))
                                                  .collect();
 
-    let init_map = {
+    // Maps each word to its column, so the output follows the order of `spec_words`
+    let columns = {
 
         let fnv = BuildHasherDefault::<FnvHasher>::default();
-        let mut init_map = HashMap::with_capacity_and_hasher(spec_words.len(), fnv);
+        let mut columns = HashMap::with_capacity_and_hasher(spec_words.len(), fnv);
 
-        for word in spec_words {
-            init_map.insert(word, 0.0);
+        for (index, word) in spec_words.iter().enumerate() {
+            columns.entry(word).or_insert(index);
         }
-        init_map
+        columns
     };
 
 
-    // time!({
     for words in text_words.iter() {
-        let mut freq_map = init_map.clone();
+        let mut freq_vec = vec![0f32; spec_words.len()];
 
         for word in words {
-            if let Some(f) = freq_map.get_mut(word) {
-                *f += 1.0;
+            if let Some(&index) = columns.get(word) {
+                freq_vec[index] += 1.0;
             }
         }
 
-        let freq_vec: Vec<_> = freq_map.values()
-                                       .cloned()
-                                       .collect();
-
         freq_matrix.push(freq_vec);
     }
 
-    // });
-
     freq_matrix
 }
 
@@ -248,6 +237,8 @@ pub fn subs_to_float(subs: &[&str]) -> Vec<f32> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use reddit::Comment;
+    use tokenizer::Tokenizer;
 
     #[test]
     fn test_rust_code_search() {
@@ -265,6 +256,27 @@ mod tests {
         assert_eq!(r[0], vec![1f32, 1f32, 1f32, 1f32]);
     }
 
+    #[test]
+    fn test_code_search_ignores_prose() {
+        let texts = vec!["let me know = when the server wipes;",
+                         "Why?\n\n```\nlet mut x = vec![1];\n```\n"];
+        let r = check_for_code_in_markdown(&texts[..]);
+        assert_eq!(r[0], vec![0f32, 0f32, 0f32, 0f32]);
+        assert_eq!(r[1], vec![0f32, 1f32, 0f32, 1f32]);
+
+        assert_eq!(markdown_code_features(&texts[..])[1], vec![1f32, 0f32, 0.25]);
+    }
+
+    #[test]
+    fn test_rust_syntax_features() {
+        let texts = vec!["Why does `impl<'a> Iterator for Iter<'a>` need a lifetime?",
+                         "My base got raided, `where` do I respawn?"];
+        let features = rust_syntax_features(&texts[..]);
+        assert_eq!(features[0][..3], [2f32, 0f32, 1f32]);
+        assert_eq!(features[0][9], 1f32);
+        assert_eq!(features[1][9], 0f32);
+    }
+
     #[test]
     fn test_symbol_freq() {
         let texts = vec!["Hey I need help with this function: pub fn get_stuff1(thing: &str) -> \
@@ -302,10 +314,48 @@ mod tests {
                                      "orange".to_owned(),
                                      "quickly".to_owned()];
 
-        let expected = vec![1f32, 2f32, 0f32, 1f32, 0f32];
+        let expected = vec![1f32, 0f32, 2f32, 0f32, 1f32];
+
+        let tokenizer = Tokenizer::default();
+        let vocabulary = tokenizer.vocabulary(&interesting_words[..]);
+        let frequencies = interesting_word_freq(&texts[..], &vocabulary[..], &tokenizer);
 
-        let frequencies = interesting_word_freq(&texts[..], &interesting_words[..]);
+        assert_eq!(expected, frequencies[0]);
+    }
+
+    #[test]
+    fn test_get_words() {
+        assert_eq!(get_words("This class has cargos, ünïcode and my_server"),
+                   vec!["class", "cargo", "ünïcode", "server"]);
+    }
+
+    #[test]
+    fn test_word_freq_follows_vocabulary_order() {
+        let texts = vec!["the lazy brown fox jumped quickly = over the lazy fence"];
+        let interesting_words = vec!["lazy".to_owned(),
+                                     "fence".to_owned(),
+                                     "quickly".to_owned(),
+                                     "juniper".to_owned(),
+                                     "orange".to_owned()];
+
+        let expected = vec![2f32, 1f32, 1f32, 0f32, 0f32];
+
+        let tokenizer = Tokenizer::default();
+        let vocabulary = tokenizer.vocabulary(&interesting_words[..]);
+        let frequencies = interesting_word_freq(&texts[..], &vocabulary[..], &tokenizer);
 
         assert_eq!(expected, frequencies[0]);
     }
+
+    #[test]
+    fn test_comment_features() {
+        let comment = |body: &str| {
+            Comment { body: body.to_owned(), ..Comment::default() }
+        };
+        let comments = vec![comment("You want /r/PlayRust"),
+                            comment("try `let x = foo();` instead"),
+                            comment("same")];
+
+        assert_eq!(comment_features(&comments[..]), vec![3f32, 1f32, 1f32]);
+    }
 }
@@ -0,0 +1,182 @@
+use error::{Error, Result};
+use tokenizer::Tokenizer;
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+/// A curated list of terms that point to one domain, e.g. raiding in the game or the borrow
+/// checker.
+///
+/// Lexicons are read from text files with one term per line. Terms are split and normalized by the
+/// same `Tokenizer` as the posts, so `base` also matches `bases`, and a term of several words
+/// matches those words in sequence. Lines starting with `#` are comments, except for the headers
+/// `# version: N`, which is required, and `# domain: NAME`.
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct Lexicon {
+    pub name: String,
+    /// Bumped whenever the terms change, so models trained on other terms are rejected
+    pub version: u32,
+    pub domain: String,
+    /// Every term as written in the file
+    pub terms: Vec<String>,
+}
+
+impl Lexicon {
+    pub fn parse(name: &str, contents: &str) -> Result<Lexicon> {
+        let mut version = None;
+        let mut domain = String::new();
+        let mut terms = Vec::new();
+
+        for line in contents.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            if line.starts_with('#') {
+                let header = line.trim_left_matches('#').trim();
+                if header.starts_with("version:") {
+                    let value = header["version:".len()..].trim();
+                    let parsed = value.parse::<u32>().map_err(|_| {
+                        Error::Config(format!("lexicon {} has invalid version `{}`", name, value))
+                    });
+                    version = Some(try!(parsed));
+                } else if header.starts_with("domain:") {
+                    domain = header["domain:".len()..].trim().to_owned();
+                }
+                continue;
+            }
+
+            let term = line.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !terms.contains(&term) {
+                terms.push(term);
+            }
+        }
+
+        Ok(Lexicon {
+            name: name.to_owned(),
+            version: try!(version.ok_or_else(|| {
+                Error::Config(format!("lexicon {} has no version header", name))
+            })),
+            domain: domain,
+            terms: terms,
+        })
+    }
+
+    /// Loads the lexicon at `path`, named after the file without its extension
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Lexicon> {
+        let path = path.as_ref();
+        let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("").to_owned();
+
+        let mut contents = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut contents));
+        Lexicon::parse(&name, &contents)
+    }
+
+    /// Every term as the tokens `tokenizer` splits it into. Terms that normalize to the tokens
+    /// of an earlier term, or to no tokens at all, are left out.
+    pub fn tokenize_terms(&self, tokenizer: &Tokenizer) -> Vec<Vec<String>> {
+        let mut terms: Vec<Vec<String>> = Vec::with_capacity(self.terms.len());
+        for term in self.terms.iter().map(|t| tokenizer.tokenize(t)) {
+            if !term.is_empty() && !terms.contains(&term) {
+                terms.push(term);
+            }
+        }
+        terms
+    }
+}
+
+/// Number of times any of the tokenized `terms` occurs in `tokens`
+fn count_hits(terms: &[Vec<String>], tokens: &[String]) -> usize {
+    terms.iter()
+         .map(|term| tokens.windows(term.len()).filter(|window| *window == &term[..]).count())
+         .sum()
+}
+
+/// Loads every `.txt` lexicon in `dir`, ordered by name
+pub fn load_lexicons<P: AsRef<Path>>(dir: P) -> Result<Vec<Lexicon>> {
+    let mut lexicons = Vec::new();
+    for entry in try!(fs::read_dir(dir)) {
+        let path = try!(entry).path();
+        if path.extension().map_or(false, |e| e == "txt") {
+            lexicons.push(try!(Lexicon::load(&path)));
+        }
+    }
+    lexicons.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(lexicons)
+}
+
+/// For every text, the number of hits of each lexicon followed by those hits per token, two
+/// columns per lexicon. Both the texts and the terms are split by `tokenizer`.
+pub fn lexicon_features(texts: &[&str],
+                        lexicons: &[Lexicon],
+                        tokenizer: &Tokenizer)
+                        -> Vec<Vec<f32>> {
+    let terms: Vec<Vec<Vec<String>>> = lexicons.iter()
+                                               .map(|l| l.tokenize_terms(tokenizer))
+                                               .collect();
+    texts.iter()
+         .map(|text| {
+             let tokens = tokenizer.tokenize(text);
+             let mut features = Vec::with_capacity(lexicons.len() * 2);
+             for lexicon_terms in &terms {
+                 let hits = count_hits(lexicon_terms, &tokens) as f32;
+                 features.push(hits);
+                 features.push(if tokens.is_empty() { 0.0 } else { hits / tokens.len() as f32 });
+             }
+             features
+         })
+         .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokenizer::Tokenizer;
+
+    fn raiding() -> Lexicon {
+        Lexicon::parse("raiding", "# version: 2\n# domain: game\nraid\nOffline raid\n\nraid\n")
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let lexicon = raiding();
+        assert_eq!(lexicon.version, 2);
+        assert_eq!(lexicon.domain, "game");
+        assert_eq!(lexicon.terms, vec!["raid".to_owned(), "Offline raid".to_owned()]);
+
+        assert!(Lexicon::parse("unversioned", "raid\n").is_err());
+    }
+
+    #[test]
+    fn test_features() {
+        let texts = vec!["Got offline raided, then an offline raid. Raid again?", ""];
+        let features = lexicon_features(&texts[..], &[raiding()], &Tokenizer::default());
+
+        // "raided" is stemmed to "raid", the stop words "then", "an" and "again" are dropped
+        assert_eq!(features[0], vec![5f32, 5f32 / 6f32]);
+        assert_eq!(features[1], vec![0f32, 0f32]);
+    }
+
+    #[test]
+    fn test_inflected_terms_are_merged() {
+        let lexicon = Lexicon::parse("bases", "# version: 1\nbase\nbases\nthe\n").unwrap();
+        assert_eq!(lexicon.tokenize_terms(&Tokenizer::default()),
+                   vec![vec!["base".to_owned()]]);
+    }
+
+    #[test]
+    fn test_bundled_lexicons() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/static_data/lexicons");
+        let lexicons = load_lexicons(dir).unwrap();
+
+        assert!(lexicons.iter().any(|l| l.domain == "game"));
+        assert!(lexicons.iter().any(|l| l.domain == "language"));
+        assert!(lexicons.windows(2).all(|pair| pair[0].name < pair[1].name));
+
+        // Every term must match something the others do not
+        let tokenizer = Tokenizer::default();
+        for lexicon in &lexicons {
+            assert!(lexicon.tokenize_terms(&tokenizer).len() == lexicon.terms.len(),
+                    "{} has terms that are the same once tokenized",
+                    lexicon.name);
+        }
+    }
+}
@@ -37,18 +37,45 @@ extern crate clap;
 extern crate csv;
 extern crate serde;
 extern crate hyper;
+extern crate pulldown_cmark;
+extern crate rand;
 extern crate rayon;
 extern crate regex;
+extern crate rusqlite;
 extern crate rustlearn;
 extern crate rustc_serialize;
 extern crate serde_json;
 extern crate stopwatch;
 extern crate tiny_keccak;
-extern crate tfidf;
+extern crate unicode_segmentation;
 extern crate fnv;
 
+pub mod alert;
+pub mod cross_validation;
+pub mod dataset;
+pub mod dedup;
+pub mod error;
+pub mod evaluation;
 pub mod feature_extraction;
+pub mod lexicon;
+pub mod markdown;
+pub mod model;
+pub mod moderation;
+pub mod oauth;
+pub mod pipeline;
+pub mod ratelimit;
 pub mod reddit;
+pub mod rust_lexer;
+pub mod scraper;
+pub mod service;
+pub mod sink;
+pub mod stemmer;
+pub mod store;
+pub mod tokenizer;
+pub mod transport;
 pub mod util;
+pub mod vectorizer;
+pub mod watcher;
 
+pub use error::{Error, Result};
 pub use stopwatch::Stopwatch;
@@ -0,0 +1,107 @@
+use pulldown_cmark::{Event, Parser, Tag};
+
+/// The code a markdown post contains, separated from its prose
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CodeRegions {
+    /// The contents of every fenced or indented code block
+    pub blocks: Vec<String>,
+    /// The contents of every inline code span
+    pub inline: Vec<String>,
+}
+
+impl CodeRegions {
+    /// All code of the post, blocks first, one region per line
+    pub fn code(&self) -> String {
+        let mut code = String::new();
+        for region in self.blocks.iter().chain(&self.inline) {
+            code.push_str(region);
+            if !region.ends_with('\n') {
+                code.push('\n');
+            }
+        }
+        code
+    }
+
+    /// Number of non blank lines inside code blocks
+    pub fn block_lines(&self) -> usize {
+        self.blocks
+            .iter()
+            .flat_map(|block| block.lines())
+            .filter(|line| !line.trim().is_empty())
+            .count()
+    }
+}
+
+/// Parses `text` as markdown and collects its code blocks and inline code
+pub fn extract_code(text: &str) -> CodeRegions {
+    let mut regions = CodeRegions::default();
+    // The region currently being read, if inside a code block or span
+    let mut current: Option<(bool, String)> = None;
+
+    for event in Parser::new(text) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => current = Some((true, String::new())),
+            Event::Start(Tag::Code) => current = Some((false, String::new())),
+            Event::Text(ref t) => {
+                if let Some((_, ref mut region)) = current {
+                    region.push_str(t);
+                }
+            }
+            Event::End(Tag::CodeBlock(_)) |
+            Event::End(Tag::Code) => {
+                if let Some((is_block, region)) = current.take() {
+                    if is_block {
+                        regions.blocks.push(region);
+                    } else {
+                        regions.inline.push(region);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    regions
+}
+
+/// Fraction of the non blank lines of `text` that are inside code blocks
+pub fn code_line_ratio(text: &str, regions: &CodeRegions) -> f32 {
+    let lines = text.lines().filter(|line| !line.trim().is_empty()).count();
+    if lines == 0 {
+        return 0.0;
+    }
+    (regions.block_lines() as f32 / lines as f32).min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fenced_and_indented_blocks() {
+        let text = "Why does this fail?\n\n```rust\nfn main() {\n    let x = 1;\n}\n```\n\nand \
+                    this:\n\n    let y = 2;\n";
+        let regions = extract_code(text);
+
+        assert_eq!(regions.blocks.len(), 2);
+        assert!(regions.blocks[0].contains("fn main()"));
+        assert_eq!(regions.blocks[1].trim(), "let y = 2;");
+        assert_eq!(regions.block_lines(), 4);
+        assert!(regions.inline.is_empty());
+    }
+
+    #[test]
+    fn test_inline_code() {
+        let regions = extract_code("Use `Vec::new()` rather than `vec![]` here");
+        assert_eq!(regions.inline, vec!["Vec::new()".to_owned(), "vec![]".to_owned()]);
+        assert_eq!(regions.code(), "Vec::new()\nvec![]\n");
+    }
+
+    #[test]
+    fn test_prose_has_no_code() {
+        let text = "let me know = whether the server wipes today;";
+        let regions = extract_code(text);
+        assert_eq!(regions, CodeRegions::default());
+        assert_eq!(code_line_ratio(text, &regions), 0.0);
+    }
+}
@@ -0,0 +1,575 @@
+use rustlearn::prelude::*;
+use rustlearn::ensemble::random_forest::RandomForest;
+use rustlearn::multiclass::OneVsRestWrapper;
+
+use error::{Error, Result};
+use evaluation::normalize_scores;
+use pipeline::FeaturePipeline;
+use reddit::RawPostFeatures;
+use util::{deserialize_from_file, serialize_to_file};
+
+use std::cmp;
+use std::error;
+use std::fmt;
+use std::result;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Bumped whenever the layout of `ModelBundle` changes
+pub const MODEL_FORMAT_VERSION: u32 = 9;
+
+pub type Forest = OneVsRestWrapper<RandomForest>;
+
+#[derive(Debug, Clone, RustcEncodable, RustcDecodable)]
+pub struct TrainingMetadata {
+    /// Seconds since the unix epoch at which the model was trained
+    pub trained_at: u64,
+    /// Number of posts the final model was fit on
+    pub training_posts: usize,
+    /// Number of trees in the forest
+    pub trees: usize,
+    /// Mean cross validated accuracy
+    pub cv_accuracy: f32,
+}
+
+impl TrainingMetadata {
+    pub fn new(training_posts: usize, trees: usize, cv_accuracy: f32) -> TrainingMetadata {
+        let trained_at = SystemTime::now()
+                             .duration_since(UNIX_EPOCH)
+                             .map(|d| d.as_secs())
+                             .unwrap_or(0);
+        TrainingMetadata {
+            trained_at: trained_at,
+            training_posts: training_posts,
+            trees: trees,
+            cv_accuracy: cv_accuracy,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModelError {
+    /// The bundle was written by an incompatible version of this crate
+    UnsupportedVersion(u32),
+    /// The bundle was trained on a feature layout or with extraction code that differs from the
+    /// current one
+    SchemaMismatch { expected: u64, found: u64 },
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ModelError::UnsupportedVersion(v) => {
+                write!(f,
+                       "model format version {} is not supported, expected {}",
+                       v,
+                       MODEL_FORMAT_VERSION)
+            }
+            ModelError::SchemaMismatch { expected, found } => {
+                write!(f,
+                       "model feature schema {:016x} does not match pipeline schema {:016x}",
+                       found,
+                       expected)
+            }
+        }
+    }
+}
+
+impl error::Error for ModelError {
+    fn description(&self) -> &str {
+        match *self {
+            ModelError::UnsupportedVersion(_) => "unsupported model format version",
+            ModelError::SchemaMismatch { .. } => "model feature schema mismatch",
+        }
+    }
+}
+
+/// The mean and standard deviation of a feature column over the training posts
+#[derive(Debug, Clone, Copy, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct ColumnStats {
+    pub mean: f32,
+    pub stddev: f32,
+}
+
+impl ColumnStats {
+    /// The statistics of every column of `x`
+    pub fn of(x: &Array) -> Vec<ColumnStats> {
+        let rows = cmp::max(x.rows(), 1) as f32;
+        (0..x.cols())
+            .map(|col| {
+                let mean = (0..x.rows()).map(|row| x.get(row, col)).sum::<f32>() / rows;
+                let variance = (0..x.rows())
+                                   .map(|row| (x.get(row, col) - mean).powi(2))
+                                   .sum::<f32>() / rows;
+                ColumnStats {
+                    mean: mean,
+                    stddev: variance.sqrt(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// How unusual one feature of a post is compared to the training posts. This describes the post
+/// rather than the prediction: a feature can stand out without the forest ever splitting on it.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Deviation {
+    pub feature: String,
+    pub value: f32,
+    /// Standard deviations between `value` and the training mean
+    pub z_score: f32,
+}
+
+/// How much one feature of a post moved the forest towards the label it predicted
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Contribution {
+    pub feature: String,
+    pub value: f32,
+    /// The probability of the predicted label minus its probability with this feature at its
+    /// training mean. Negative if the feature argued against the label.
+    pub contribution: f32,
+}
+
+/// Whether the model committed to a label for a post
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Label(String),
+    /// The most probable label was below its threshold
+    Abstain,
+}
+
+/// The prediction for a single post
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prediction {
+    /// The most probable label
+    pub label: String,
+    /// The probability of `label`
+    pub probability: f32,
+    pub outcome: Outcome,
+}
+
+/// A trained forest packaged with the feature pipeline it was trained against.
+///
+/// This is the single artifact written by `model_generator` and read by `predictor`.
+#[derive(RustcEncodable, RustcDecodable)]
+pub struct ModelBundle {
+    pub version: u32,
+    pub schema_hash: u64,
+    pub pipeline: FeaturePipeline,
+    pub forest: Forest,
+    pub metadata: TrainingMetadata,
+    /// The probability each label must reach to be predicted, indexed by label
+    pub thresholds: Vec<f32>,
+    /// Statistics of every feature column over the training posts, empty if not recorded
+    pub feature_stats: Vec<ColumnStats>,
+}
+
+impl ModelBundle {
+    pub fn new(pipeline: FeaturePipeline,
+               forest: Forest,
+               metadata: TrainingMetadata)
+               -> ModelBundle {
+        // Never abstain until thresholds are tuned
+        let thresholds = vec![0.0; pipeline.labels().len()];
+        ModelBundle {
+            version: MODEL_FORMAT_VERSION,
+            schema_hash: pipeline.schema_hash(),
+            pipeline: pipeline,
+            forest: forest,
+            metadata: metadata,
+            thresholds: thresholds,
+            feature_stats: vec![],
+        }
+    }
+
+    /// Records the column statistics of the training matrix `x`, which `unusual_features`
+    /// compares posts against. `x` must have a column per feature of the pipeline.
+    pub fn with_feature_stats(mut self, x: &Array) -> Result<ModelBundle> {
+        let columns = self.pipeline.feature_names().len();
+        if x.cols() != columns {
+            return Err(Error::Config(format!("statistics of {} columns given for {} features",
+                                             x.cols(),
+                                             columns)));
+        }
+        self.feature_stats = ColumnStats::of(x);
+        Ok(self)
+    }
+
+    /// Replaces the per label thresholds, e.g. with those found by
+    /// `evaluation::tune_thresholds`. There must be a threshold per label.
+    pub fn with_thresholds(mut self, thresholds: Vec<f32>) -> Result<ModelBundle> {
+        let labels = self.pipeline.labels().len();
+        if thresholds.len() != labels {
+            return Err(Error::Config(format!("{} thresholds given for {} labels",
+                                             thresholds.len(),
+                                             labels)));
+        }
+        self.thresholds = thresholds;
+        Ok(self)
+    }
+
+    /// Overrides the threshold of `label`. Returns false if the model has no such label.
+    pub fn set_threshold(&mut self, label: &str, threshold: f32) -> bool {
+        match self.pipeline.label_of(label) {
+            Some(l) => {
+                self.thresholds[l as usize] = threshold;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Loads a bundle from `path`, refusing it if it was written with a different format
+    /// version or feature schema
+    pub fn load(path: &str) -> Result<ModelBundle> {
+        let bundle: ModelBundle = try!(deserialize_from_file(path));
+        try!(bundle.check());
+        Ok(bundle)
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        serialize_to_file(self, path)
+    }
+
+    /// Refuses bundles of another format version, and bundles whose schema hash was computed by
+    /// different feature extraction code than the running binary's
+    pub fn check(&self) -> result::Result<(), ModelError> {
+        if self.version != MODEL_FORMAT_VERSION {
+            return Err(ModelError::UnsupportedVersion(self.version));
+        }
+
+        let expected = self.pipeline.schema_hash();
+        if self.schema_hash != expected {
+            return Err(ModelError::SchemaMismatch {
+                expected: expected,
+                found: self.schema_hash,
+            });
+        }
+        Ok(())
+    }
+
+    /// Transforms `raw_posts` with the bundled pipeline and predicts a label for each
+    pub fn predict(&self, raw_posts: &[RawPostFeatures]) -> Result<Array> {
+        let features = self.pipeline.transform(raw_posts);
+        self.forest.predict(&features).map_err(Error::Learner)
+    }
+
+    /// The probability of every label for each post, one row per post with a column per label.
+    /// The forest's per label scores, averaged across its trees, are normalized to sum to one.
+    pub fn predict_proba(&self, raw_posts: &[RawPostFeatures]) -> Result<Array> {
+        self.probabilities(&self.pipeline.transform(raw_posts))
+    }
+
+    /// `predict_proba` on already transformed features
+    fn probabilities(&self, features: &Array) -> Result<Array> {
+        let scores = try!(self.forest.decision_function(features).map_err(Error::Learner));
+
+        let mut probabilities = Vec::with_capacity(scores.rows() * scores.cols());
+        for row in 0..scores.rows() {
+            let row: Vec<f32> = (0..scores.cols()).map(|col| scores.get(row, col)).collect();
+            probabilities.extend(normalize_scores(&row));
+        }
+
+        let mut probabilities = Array::from(probabilities);
+        probabilities.reshape(scores.rows(), scores.cols());
+        Ok(probabilities)
+    }
+
+    /// Predicts the most probable label of every post, abstaining where it falls below the
+    /// label's threshold
+    pub fn classify(&self, raw_posts: &[RawPostFeatures]) -> Result<Vec<Prediction>> {
+        if raw_posts.is_empty() {
+            return Ok(vec![]);
+        }
+        let probabilities = try!(self.predict_proba(raw_posts));
+
+        Ok((0..probabilities.rows())
+               .map(|row| {
+                   let best = most_probable(&probabilities, row);
+                   let label = self.pipeline.label_name(best as f32).unwrap_or("").to_owned();
+                   let probability = probabilities.get(row, best);
+
+                   let outcome = if probability >= self.thresholds[best] {
+                       Outcome::Label(label.clone())
+                   } else {
+                       Outcome::Abstain
+                   };
+
+                   Prediction {
+                       label: label,
+                       probability: probability,
+                       outcome: outcome,
+                   }
+               })
+               .collect())
+    }
+
+    /// The `n` features of every post furthest from their training mean, measured in standard
+    /// deviations. Columns that were constant during training are skipped, as is everything if
+    /// the bundle has no feature statistics.
+    ///
+    /// These are not the features the forest based its prediction on, only the ones in which
+    /// the post differs most from the training posts.
+    pub fn unusual_features(&self,
+                            raw_posts: &[RawPostFeatures],
+                            n: usize)
+                            -> Vec<Vec<Deviation>> {
+        if raw_posts.is_empty() {
+            return vec![];
+        }
+        let features = self.pipeline.transform(raw_posts);
+        let names = self.pipeline.feature_names();
+
+        (0..features.rows())
+            .map(|row| {
+                let mut deviations: Vec<Deviation> =
+                    self.feature_stats
+                        .iter()
+                        .enumerate()
+                        .filter(|&(_, stats)| stats.stddev > 0.0)
+                        .map(|(col, stats)| {
+                            let value = features.get(row, col);
+                            Deviation {
+                                feature: names[col].clone(),
+                                value: value,
+                                z_score: (value - stats.mean) / stats.stddev,
+                            }
+                        })
+                        .collect();
+                deviations.sort_by(|a, b| {
+                    b.z_score
+                     .abs()
+                     .partial_cmp(&a.z_score.abs())
+                     .unwrap_or(cmp::Ordering::Equal)
+                });
+                deviations.truncate(n);
+                deviations
+            })
+            .collect()
+    }
+
+    /// The `n` features that contributed most to the predicted label of every post, strongest
+    /// first. A feature's contribution is how much the probability of the predicted label drops
+    /// when only that feature is set to its training mean, so features the forest does not
+    /// split on contribute nothing and are left out. Empty if the bundle has no feature
+    /// statistics.
+    ///
+    /// rustlearn keeps the nodes of its trees private, so instead of walking the decision paths
+    /// this asks the forest itself, one prediction per feature that differs from its mean.
+    pub fn feature_contributions(&self,
+                                 raw_posts: &[RawPostFeatures],
+                                 n: usize)
+                                 -> Result<Vec<Vec<Contribution>>> {
+        if raw_posts.is_empty() {
+            return Ok(vec![]);
+        }
+        let features = self.pipeline.transform(raw_posts);
+        let probabilities = try!(self.probabilities(&features));
+        let names = self.pipeline.feature_names();
+
+        let mut contributions = Vec::with_capacity(features.rows());
+        for row in 0..features.rows() {
+            let values: Vec<f32> = (0..features.cols()).map(|col| features.get(row, col)).collect();
+            let columns: Vec<usize> = self.feature_stats
+                                          .iter()
+                                          .enumerate()
+                                          .filter(|&(col, stats)| values[col] != stats.mean)
+                                          .map(|(col, _)| col)
+                                          .collect();
+            if columns.is_empty() {
+                contributions.push(vec![]);
+                continue;
+            }
+
+            // One copy of the post per column, with that column reset to its mean
+            let mut reset = Vec::with_capacity(columns.len() * values.len());
+            for &col in &columns {
+                let start = reset.len();
+                reset.extend_from_slice(&values);
+                reset[start + col] = self.feature_stats[col].mean;
+            }
+            let mut reset = Array::from(reset);
+            reset.reshape(columns.len(), values.len());
+            let reset_probabilities = try!(self.probabilities(&reset));
+
+            let label = most_probable(&probabilities, row);
+            let probability = probabilities.get(row, label);
+            let mut post_contributions: Vec<Contribution> =
+                columns.iter()
+                       .enumerate()
+                       .map(|(i, &col)| {
+                           Contribution {
+                               feature: names[col].clone(),
+                               value: values[col],
+                               contribution: probability - reset_probabilities.get(i, label),
+                           }
+                       })
+                       .filter(|c| c.contribution != 0.0)
+                       .collect();
+            post_contributions.sort_by(|a, b| {
+                b.contribution
+                 .abs()
+                 .partial_cmp(&a.contribution.abs())
+                 .unwrap_or(cmp::Ordering::Equal)
+            });
+            post_contributions.truncate(n);
+            contributions.push(post_contributions);
+        }
+        Ok(contributions)
+    }
+}
+
+/// The column of the most probable label in `row`, the first on ties
+fn most_probable(probabilities: &Array, row: usize) -> usize {
+    (0..probabilities.cols()).fold(0, |best, col| {
+        if probabilities.get(row, col) > probabilities.get(row, best) {
+            col
+        } else {
+            best
+        }
+    })
+}
+
+/// A bundle fit on two posts, one of /r/rust and one of /r/playrust, for tests that need a
+/// working model
+#[cfg(test)]
+pub fn test_bundle() -> ModelBundle {
+    use rustlearn::ensemble::random_forest::Hyperparameters;
+    use rustlearn::trees::decision_tree;
+
+    let post = |subreddit: &str, selftext: &str| {
+        RawPostFeatures {
+            selftext: selftext.to_owned(),
+            subreddit: subreddit.to_owned(),
+            ..RawPostFeatures::default()
+        }
+    };
+    let posts = vec![post("rust", "fn main() { let x = 1; }"), post("playrust", "wipe")];
+    let (pipeline, x, y) = FeaturePipeline::fit_transform(&posts[..], vec![]);
+
+    let tree_params = decision_tree::Hyperparameters::new(x.cols());
+    let mut forest = Hyperparameters::new(tree_params, 2).one_vs_rest();
+    forest.fit(&x, &y).unwrap();
+
+    ModelBundle::new(pipeline, forest, TrainingMetadata::new(posts.len(), 2, 1.0))
+        .with_feature_stats(&x)
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use error::Error;
+    use pipeline::FEATURE_SCHEMA_VERSION;
+    use reddit::RawPostFeatures;
+    use util::temp_dir;
+
+    use rustlearn::prelude::*;
+
+    use std::fs;
+
+    fn post(subreddit: &str, selftext: &str) -> RawPostFeatures {
+        RawPostFeatures {
+            is_self: true,
+            author: "author".to_owned(),
+            url: String::new(),
+            downs: 0,
+            ups: 1,
+            score: 1,
+            selftext: selftext.to_owned(),
+            subreddit: subreddit.to_owned(),
+            title: "title".to_owned(),
+            ..RawPostFeatures::default()
+        }
+    }
+
+    #[test]
+    fn test_check_accepts_fresh_bundle() {
+        assert_eq!(test_bundle().check(), Ok(()));
+    }
+
+    #[test]
+    fn test_classify() {
+        let posts = vec![post("rust", "fn main() { let x = 1; }")];
+        let bundle = test_bundle();
+
+        let probabilities = bundle.predict_proba(&posts).unwrap();
+        assert_eq!(probabilities.cols(), 2);
+        let total = probabilities.get(0, 0) + probabilities.get(0, 1);
+        assert!((total - 1.0).abs() < 1e-6);
+
+        let prediction = bundle.classify(&posts).unwrap().remove(0);
+        assert_eq!(prediction.outcome, Outcome::Label(prediction.label.clone()));
+
+        assert!(test_bundle().with_thresholds(vec![0.5]).is_err());
+        let bundle = bundle.with_thresholds(vec![1.1, 1.1]).unwrap();
+        assert_eq!(bundle.classify(&posts).unwrap()[0].outcome, Outcome::Abstain);
+    }
+
+    #[test]
+    fn test_unusual_features() {
+        let posts = vec![post("rust", "fn main() { let x = 1; }")];
+        let deviations = test_bundle().unusual_features(&posts, 3).remove(0);
+
+        assert!(!deviations.is_empty() && deviations.len() <= 3);
+        for pair in deviations.windows(2) {
+            assert!(pair[0].z_score.abs() >= pair[1].z_score.abs());
+        }
+    }
+
+    #[test]
+    fn test_feature_contributions() {
+        let posts = vec![post("playrust", "wipe"), post("rust", "fn main() { let x = 1; }")];
+        let bundle = test_bundle();
+        let contributions = bundle.feature_contributions(&posts, 3).unwrap();
+        assert_eq!(contributions.len(), 2);
+
+        let names = bundle.pipeline.feature_names();
+        for post_contributions in &contributions {
+            assert!(post_contributions.len() <= 3);
+            assert!(post_contributions.iter().all(|c| c.contribution != 0.0));
+            assert!(post_contributions.iter().all(|c| names.contains(&c.feature)));
+            for pair in post_contributions.windows(2) {
+                assert!(pair[0].contribution.abs() >= pair[1].contribution.abs());
+            }
+        }
+
+        let mut without_stats = test_bundle();
+        without_stats.feature_stats.clear();
+        let contributions = without_stats.feature_contributions(&posts, 3).unwrap();
+        assert!(contributions.iter().all(|c| c.is_empty()));
+    }
+
+    #[test]
+    fn test_check_rejects_schema_mismatch() {
+        let mut bundle = test_bundle();
+        bundle.schema_hash ^= 1;
+        match bundle.check() {
+            Err(ModelError::SchemaMismatch { .. }) => (),
+            other => panic!("Expected a schema mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_load_rejects_bundle_from_older_extraction_code() {
+        let dir = temp_dir("model_stale_schema");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("model_bundle");
+        let path = path.to_str().unwrap();
+
+        // As saved by a binary built before the last change to feature extraction
+        let mut stale = test_bundle();
+        stale.schema_hash = stale.pipeline.schema_hash_for(FEATURE_SCHEMA_VERSION - 1);
+        stale.save(path).unwrap();
+
+        match ModelBundle::load(path) {
+            Err(Error::Model(ModelError::SchemaMismatch { expected, found })) => {
+                assert_eq!(expected, stale.pipeline.schema_hash());
+                assert_eq!(found, stale.schema_hash);
+            }
+            other => panic!("Expected a schema mismatch, got {:?}", other.map(|b| b.version)),
+        }
+
+        test_bundle().save(path).unwrap();
+        assert!(ModelBundle::load(path).is_ok());
+    }
+}
@@ -0,0 +1,339 @@
+use serde_json::{self, Value};
+
+use alert::Alert;
+use error::{Error, Result};
+use model::ModelBundle;
+use reddit::RedditClient;
+use transport::Transport;
+
+use std::fs::File;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What to do about a flagged post, from least to most intrusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    /// Report the post to the modqueue
+    Report,
+    /// Reply with a sticky comment pointing the author to the right subreddit
+    Reply,
+    /// Remove the post and reply with the removal reason
+    Remove,
+}
+
+impl Action {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Action::Report => "report",
+            Action::Reply => "reply",
+            Action::Remove => "remove",
+        }
+    }
+}
+
+fn default_dry_run() -> bool {
+    true
+}
+
+/// The confidence tiers that trigger each action and the texts they use, as read from a
+/// deployment's JSON configuration. A tier without a probability is never triggered.
+///
+/// Templates may contain `{title}`, `{author}`, `{label}`, `{probability}` and `{permalink}`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ModerationPolicy {
+    #[serde(default)]
+    pub report_above: Option<f32>,
+    #[serde(default)]
+    pub reply_above: Option<f32>,
+    #[serde(default)]
+    pub remove_above: Option<f32>,
+    pub report_reason: String,
+    pub reply_template: String,
+    pub removal_reason: String,
+    /// Only log the actions that would be taken. Defaults to true so a new policy can be
+    /// reviewed before it touches any post.
+    #[serde(default="default_dry_run")]
+    pub dry_run: bool,
+}
+
+impl ModerationPolicy {
+    pub fn load(path: &str) -> Result<ModerationPolicy> {
+        Ok(try!(serde_json::from_reader(try!(File::open(path)))))
+    }
+
+    /// The most intrusive action whose tier `probability` reaches
+    pub fn action_for(&self, probability: f32) -> Option<Action> {
+        let tiers = [(self.remove_above, Action::Remove),
+                     (self.reply_above, Action::Reply),
+                     (self.report_above, Action::Report)];
+        tiers.iter()
+             .find(|&&(above, _)| above.map_or(false, |above| probability >= above))
+             .map(|&(_, action)| action)
+    }
+}
+
+/// Fills the placeholders of a template with the details of `alert`
+pub fn render(template: &str, alert: &Alert) -> String {
+    template.replace("{title}", &alert.title)
+            .replace("{author}", &alert.author)
+            .replace("{label}", &alert.label)
+            .replace("{probability}", &format!("{:.2}", alert.probability))
+            .replace("{permalink}", &alert.url())
+}
+
+/// One line of the audit log, written for every action taken or attempted
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// Seconds since the unix epoch
+    pub at: u64,
+    pub post: String,
+    pub action: String,
+    pub label: String,
+    pub probability: f32,
+    pub dry_run: bool,
+    /// The format version, feature schema and training time of the model that flagged the post
+    pub model_version: u32,
+    pub model_schema: String,
+    pub model_trained_at: u64,
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Takes the action a policy prescribes for every alert and records it in an audit log
+pub struct Moderator<W: Write> {
+    policy: ModerationPolicy,
+    model_version: u32,
+    model_schema: String,
+    model_trained_at: u64,
+    audit: W,
+}
+
+impl<W: Write> Moderator<W> {
+    pub fn new(policy: ModerationPolicy, bundle: &ModelBundle, audit: W) -> Moderator<W> {
+        Moderator {
+            policy: policy,
+            model_version: bundle.version,
+            model_schema: format!("{:016x}", bundle.schema_hash),
+            model_trained_at: bundle.metadata.trained_at,
+            audit: audit,
+        }
+    }
+
+    /// Overrides the policy's dry run setting
+    pub fn dry_run(mut self, dry_run: bool) -> Moderator<W> {
+        self.policy.dry_run = dry_run;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.audit
+    }
+
+    /// Takes the action for `alert`, if any, and returns its audit entry. A failed action is
+    /// logged before its error is returned.
+    pub fn act<T: Transport>(&mut self,
+                             client: &mut RedditClient<T>,
+                             alert: &Alert)
+                             -> Result<Option<AuditEntry>> {
+        let action = match self.policy.action_for(alert.probability) {
+            Some(action) => action,
+            None => return Ok(None),
+        };
+
+        let result = if self.policy.dry_run {
+            Ok(())
+        } else {
+            self.perform(client, action, alert)
+        };
+
+        let entry = AuditEntry {
+            at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            post: fullname(alert),
+            action: action.as_str().to_owned(),
+            label: alert.label.clone(),
+            probability: alert.probability,
+            dry_run: self.policy.dry_run,
+            model_version: self.model_version,
+            model_schema: self.model_schema.clone(),
+            model_trained_at: self.model_trained_at,
+            error: result.as_ref().err().map(|e| e.to_string()),
+        };
+        try!(writeln!(self.audit, "{}", try!(serde_json::to_string(&entry))));
+        try!(self.audit.flush());
+
+        result.map(|_| Some(entry))
+    }
+
+    fn perform<T: Transport>(&self,
+                             client: &mut RedditClient<T>,
+                             action: Action,
+                             alert: &Alert)
+                             -> Result<()> {
+        let post = fullname(alert);
+        match action {
+            Action::Report => {
+                let reason = render(&self.policy.report_reason, alert);
+                try!(client.post_form("/api/report",
+                                      &[("thing_id", post.as_str()), ("reason", reason.as_str())]));
+            }
+            Action::Reply => {
+                try!(sticky_reply(client, &post, &render(&self.policy.reply_template, alert)));
+            }
+            Action::Remove => {
+                try!(client.post_form("/api/remove", &[("id", post.as_str()), ("spam", "false")]));
+                try!(sticky_reply(client, &post, &render(&self.policy.removal_reason, alert)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The fullname reddit's API identifies a post by
+fn fullname(alert: &Alert) -> String {
+    format!("t3_{}", alert.id)
+}
+
+/// Comments on `post` and pins the comment to the top as a moderator
+fn sticky_reply<T: Transport>(client: &mut RedditClient<T>, post: &str, text: &str) -> Result<()> {
+    let response = try!(client.post_form("/api/comment", &[("thing_id", post), ("text", text)]));
+    let comment = try!(response.find_path(&["json", "data", "things"])
+                               .and_then(Value::as_array)
+                               .and_then(|things| things.first())
+                               .and_then(|thing| thing.find_path(&["data", "name"]))
+                               .and_then(Value::as_str)
+                               .ok_or(Error::MissingField("things")))
+                      .to_owned();
+
+    try!(client.post_form("/api/distinguish",
+                          &[("id", comment.as_str()), ("how", "yes"), ("sticky", "true")]));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alert::Alert;
+    use error::Error;
+    use model::MODEL_FORMAT_VERSION;
+    use ratelimit::RateLimit;
+    use reddit::RedditClient;
+    use transport::{FixtureTransport, Method, Response};
+
+    use serde_json::{self, Value};
+
+    /// Only the model's identity ends up in the audit log, so no model is needed
+    fn moderator() -> Moderator<Vec<u8>> {
+        Moderator {
+            policy: policy(),
+            model_version: MODEL_FORMAT_VERSION,
+            model_schema: "00000000deadbeef".to_owned(),
+            model_trained_at: 1469750000,
+            audit: vec![],
+        }
+    }
+
+    fn policy() -> ModerationPolicy {
+        serde_json::from_str(r#"{"report_above": 0.6, "reply_above": 0.8, "remove_above": 0.95,
+                                 "report_reason": "Likely /r/{label} ({probability})",
+                                 "reply_template": "Hi /u/{author}, try /r/playrust",
+                                 "removal_reason": "Removed: this is about the game"}"#)
+            .unwrap()
+    }
+
+    fn alert(probability: f32) -> Alert {
+        Alert {
+            id: "4u0002".to_owned(),
+            title: "Any good servers?".to_owned(),
+            author: "raider".to_owned(),
+            permalink: "/r/rust/comments/4u0002/post/".to_owned(),
+            created_utc: 1469750500.0,
+            label: "playrust".to_owned(),
+            probability: probability,
+            contributions: vec![],
+            unusual_features: vec![],
+        }
+    }
+
+    fn client() -> RedditClient<FixtureTransport> {
+        let comment = r#"{"json": {"errors": [], "data": {"things": [{"kind": "t1",
+                                   "data": {"name": "t1_c1"}}]}}}"#;
+        let mut transport = FixtureTransport::new();
+        for path in &["/api/report", "/api/remove", "/api/distinguish"] {
+            transport.insert(Method::Post, path, Response::ok("{}".to_owned()));
+        }
+        transport.insert(Method::Post, "/api/comment", Response::ok(comment.to_owned()));
+        RedditClient::with_transport(transport).rate_limit(RateLimit::unlimited())
+    }
+
+    fn paths(client: &RedditClient<FixtureTransport>) -> Vec<String> {
+        client.transport()
+              .requests()
+              .iter()
+              .map(|r| r.url.trim_left_matches("https://www.reddit.com").to_owned())
+              .collect()
+    }
+
+    #[test]
+    fn test_tiers() {
+        let policy = policy();
+        assert_eq!(policy.action_for(0.5), None);
+        assert_eq!(policy.action_for(0.7), Some(Action::Report));
+        assert_eq!(policy.action_for(0.9), Some(Action::Reply));
+        assert_eq!(policy.action_for(0.99), Some(Action::Remove));
+        assert!(policy.dry_run);
+    }
+
+    #[test]
+    fn test_dry_run_only_audits() {
+        let mut client = client();
+        let mut moderator = moderator();
+
+        let entry = moderator.act(&mut client, &alert(0.99)).unwrap().unwrap();
+        assert_eq!(entry.action, "remove");
+        assert!(entry.dry_run);
+        assert!(client.transport().requests().is_empty());
+
+        let log = String::from_utf8(moderator.into_inner()).unwrap();
+        let line: Value = serde_json::from_str(log.trim()).unwrap();
+        assert_eq!(line.find("model_version").and_then(|v| v.as_u64()),
+                   Some(MODEL_FORMAT_VERSION as u64));
+    }
+
+    #[test]
+    fn test_actions() {
+        let mut client = client();
+        let mut moderator = moderator().dry_run(false);
+
+        moderator.act(&mut client, &alert(0.7)).unwrap();
+        assert_eq!(paths(&client), vec!["/api/report"]);
+        assert_eq!(client.transport().requests()[0].body,
+                   Some("thing_id=t3_4u0002&reason=Likely+%2Fr%2Fplayrust+%280.70%29&\
+                         api_type=json"
+                            .to_owned()));
+
+        moderator.act(&mut client, &alert(0.99)).unwrap();
+        assert_eq!(&paths(&client)[1..],
+                   &["/api/remove", "/api/comment", "/api/distinguish"]);
+        assert!(client.transport().requests()[3]
+                      .body
+                      .as_ref()
+                      .map_or(false, |b| b.starts_with("id=t1_c1&how=yes&sticky=true")));
+
+        assert!(moderator.act(&mut client, &alert(0.1)).unwrap().is_none());
+        assert_eq!(moderator.into_inner().iter().filter(|&&b| b == b'\n').count(), 2);
+    }
+
+    #[test]
+    fn test_failures_are_audited() {
+        let mut client = RedditClient::with_transport(FixtureTransport::new())
+                             .rate_limit(RateLimit::unlimited());
+        let mut moderator = moderator().dry_run(false);
+
+        match moderator.act(&mut client, &alert(0.7)) {
+            Err(Error::Status(404)) => (),
+            other => panic!("Expected a 404, got {:?}", other),
+        }
+        let log = String::from_utf8(moderator.into_inner()).unwrap();
+        assert!(log.contains("\"error\""));
+    }
+}
@@ -0,0 +1,248 @@
+use rustc_serialize::base64::{ToBase64, STANDARD};
+use serde_json;
+
+use error::{Error, Result};
+use transport::{Request, Transport, form_encode};
+
+use std::env;
+use std::fs::File;
+use std::io::prelude::*;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_TOKEN_URL: &'static str = "https://www.reddit.com/api/v1/access_token";
+
+/// The host authenticated requests must be sent to
+pub const OAUTH_BASE_URL: &'static str = "https://oauth.reddit.com";
+
+/// Tokens are refreshed this long before reddit would expire them
+const EXPIRY_MARGIN_SECS: u64 = 60;
+
+/// The credentials of a reddit app.
+///
+/// With a username and password the script app password grant is used and requests act on
+/// behalf of that account, otherwise the app authenticates on its own with client credentials.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Credentials {
+    pub client_id: String,
+    #[serde(default)]
+    pub client_secret: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+impl Credentials {
+    /// Reads credentials from a JSON file with the fields of `Credentials`
+    pub fn from_file(path: &str) -> Result<Credentials> {
+        let mut contents = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut contents));
+        Ok(try!(serde_json::from_str(&contents)))
+    }
+
+    /// Reads `REDDIT_CLIENT_ID`, `REDDIT_CLIENT_SECRET`, `REDDIT_USERNAME` and
+    /// `REDDIT_PASSWORD`. Returns `None` when no client id is set.
+    pub fn from_env() -> Option<Credentials> {
+        env::var("REDDIT_CLIENT_ID").ok().map(|client_id| {
+            Credentials {
+                client_id: client_id,
+                client_secret: env::var("REDDIT_CLIENT_SECRET").unwrap_or(String::new()),
+                username: env::var("REDDIT_USERNAME").ok(),
+                password: env::var("REDDIT_PASSWORD").ok(),
+            }
+        })
+    }
+
+    /// Reads credentials from `path` if given, falling back to the environment
+    pub fn load(path: Option<&str>) -> Result<Option<Credentials>> {
+        match path {
+            Some(path) => Credentials::from_file(path).map(Some),
+            None => Ok(Credentials::from_env()),
+        }
+    }
+
+    fn grant(&self) -> Vec<(&str, &str)> {
+        match (self.username.as_ref(), self.password.as_ref()) {
+            (Some(username), Some(password)) => {
+                vec![("grant_type", "password"),
+                     ("username", username.as_str()),
+                     ("password", password.as_str())]
+            }
+            _ => vec![("grant_type", "client_credentials")],
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct TokenResponse {
+    #[serde(default)]
+    access_token: Option<String>,
+    #[serde(default)]
+    expires_in: Option<u64>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    access_token: String,
+    refresh_at: Instant,
+}
+
+/// Obtains and refreshes bearer tokens for `RedditClient`
+#[derive(Debug, Clone)]
+pub struct OAuth {
+    credentials: Credentials,
+    token_url: String,
+    token: Option<Token>,
+}
+
+impl OAuth {
+    pub fn new(credentials: Credentials) -> OAuth {
+        OAuth {
+            credentials: credentials,
+            token_url: DEFAULT_TOKEN_URL.to_owned(),
+            token: None,
+        }
+    }
+
+    /// Requests tokens from `token_url` instead of `DEFAULT_TOKEN_URL`
+    pub fn token_url(mut self, token_url: &str) -> OAuth {
+        self.token_url = token_url.to_owned();
+        self
+    }
+
+    /// Forgets the current token, e.g. after reddit rejected it
+    pub fn invalidate(&mut self) {
+        self.token = None;
+    }
+
+    fn token_request(&self, user_agent: &str) -> Request {
+        let basic = format!("{}:{}",
+                            self.credentials.client_id,
+                            self.credentials.client_secret);
+        Request::post(&self.token_url, form_encode(&self.credentials.grant()))
+            .header("Authorization", &format!("Basic {}", basic.as_bytes().to_base64(STANDARD)))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("User-Agent", user_agent)
+    }
+
+    /// Returns a bearer token, requesting a new one if there is none yet or it is about to
+    /// expire
+    pub fn bearer_token<T: Transport>(&mut self,
+                                      transport: &mut T,
+                                      user_agent: &str)
+                                      -> Result<String> {
+        if let Some(ref token) = self.token {
+            if Instant::now() < token.refresh_at {
+                return Ok(token.access_token.clone());
+            }
+        }
+
+        let res = try!(transport.send(&self.token_request(user_agent)));
+        if !res.is_success() {
+            return Err(Error::Status(res.status));
+        }
+
+        let token: TokenResponse = try!(serde_json::from_str(&res.body));
+        if let Some(error) = token.error {
+            return Err(Error::Auth(error));
+        }
+
+        let access_token = try!(token.access_token.ok_or(Error::MissingField("access_token")));
+        let expires_in = token.expires_in.unwrap_or(0).saturating_sub(EXPIRY_MARGIN_SECS);
+
+        self.token = Some(Token {
+            access_token: access_token.clone(),
+            refresh_at: Instant::now() + Duration::from_secs(expires_in),
+        });
+        Ok(access_token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::EXPIRY_MARGIN_SECS;
+    use error::Error;
+    use transport::{FixtureTransport, Method, Response};
+
+    const TOKEN_URL: &'static str = "http://127.0.0.1:8080/api/v1/access_token";
+
+    fn credentials() -> Credentials {
+        Credentials {
+            client_id: "id".to_owned(),
+            client_secret: "secret".to_owned(),
+            username: Some("user".to_owned()),
+            password: Some("p&ss".to_owned()),
+        }
+    }
+
+    fn token_transport(expires_in: u64) -> FixtureTransport {
+        let mut transport = FixtureTransport::new();
+        let body = format!(r#"{{"access_token": "abc", "token_type": "bearer",
+                                 "expires_in": {}, "scope": "*"}}"#,
+                           expires_in);
+        transport.insert(Method::Post, TOKEN_URL, Response::ok(body));
+        transport
+    }
+
+    #[test]
+    fn test_password_grant() {
+        let mut transport = token_transport(3600);
+        let mut oauth = OAuth::new(credentials()).token_url(TOKEN_URL);
+
+        assert_eq!(oauth.bearer_token(&mut transport, "agent").unwrap(), "abc");
+
+        let request = &transport.requests()[0];
+        assert_eq!(request.body,
+                   Some("grant_type=password&username=user&password=p%26ss".to_owned()));
+        assert!(request.headers
+                       .contains(&("Authorization".to_owned(), "Basic aWQ6c2VjcmV0".to_owned())));
+    }
+
+    #[test]
+    fn test_client_credentials_grant() {
+        let mut transport = token_transport(3600);
+        let credentials = Credentials {
+            username: None,
+            password: None,
+            ..credentials()
+        };
+        let mut oauth = OAuth::new(credentials).token_url(TOKEN_URL);
+        oauth.bearer_token(&mut transport, "agent").unwrap();
+
+        assert_eq!(transport.requests()[0].body,
+                   Some("grant_type=client_credentials".to_owned()));
+    }
+
+    #[test]
+    fn test_token_is_reused_until_expiry() {
+        let mut transport = token_transport(3600);
+        let mut oauth = OAuth::new(credentials()).token_url(TOKEN_URL);
+        oauth.bearer_token(&mut transport, "agent").unwrap();
+        oauth.bearer_token(&mut transport, "agent").unwrap();
+        assert_eq!(transport.requests().len(), 1);
+
+        // Tokens expiring within the margin are refreshed on every use
+        let mut transport = token_transport(EXPIRY_MARGIN_SECS);
+        let mut oauth = OAuth::new(credentials()).token_url(TOKEN_URL);
+        oauth.bearer_token(&mut transport, "agent").unwrap();
+        oauth.bearer_token(&mut transport, "agent").unwrap();
+        assert_eq!(transport.requests().len(), 2);
+    }
+
+    #[test]
+    fn test_rejected_grant() {
+        let mut transport = FixtureTransport::new();
+        transport.insert(Method::Post,
+                         TOKEN_URL,
+                         Response::ok(r#"{"error": "invalid_grant"}"#.to_owned()));
+        let mut oauth = OAuth::new(credentials()).token_url(TOKEN_URL);
+
+        match oauth.bearer_token(&mut transport, "agent") {
+            Err(Error::Auth(ref e)) if e == "invalid_grant" => (),
+            other => panic!("Expected an auth error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,384 @@
+use rustlearn::prelude::*;
+
+use feature_extraction::{convert_is_self, check_for_code_in_markdown, interesting_word_freq,
+                         markdown_code_features, rust_syntax_features, symbol_counts, SYMBOLS,
+                         CODE_REGEX_NAMES, MARKDOWN_FEATURE_NAMES};
+use lexicon::{Lexicon, lexicon_features};
+use rust_lexer::RUST_CONSTRUCT_NAMES;
+use tokenizer::Tokenizer;
+use vectorizer::{Norm, TfScheme, TfidfVectorizer};
+use reddit::{RawPostFeatures, ProcessedPostFeatures};
+
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+
+use fnv::FnvHasher;
+
+/// The subreddit whose authors are counted for the author popularity feature
+pub const POSITIVE_SUB: &'static str = "rust";
+
+/// Bumped whenever a feature column is computed differently, even if no column is renamed. It is
+/// part of `FeaturePipeline::schema_hash`, so bundles trained with older extraction code are
+/// refused instead of being fed features they were not trained on.
+pub const FEATURE_SCHEMA_VERSION: u32 = 2;
+
+/// The fixed columns that precede the word, symbol and regex columns of the feature matrix
+const BASE_COLUMNS: [&'static str; 5] = ["author_popularity", "downs", "ups", "score", "post_len"];
+
+/// Feature extraction state learned from a training set.
+///
+/// The pipeline is fit once on the training posts and then used to transform both training and
+/// inference batches, so the two can never disagree on the column layout of the feature matrix.
+#[derive(Debug, Clone, RustcEncodable, RustcDecodable)]
+pub struct FeaturePipeline {
+    /// Number of training posts made by every author who posted to `POSITIVE_SUB`
+    author_popularity: BTreeMap<String, f32>,
+    /// Normalized vocabulary for the word frequency columns, in column order
+    words: Vec<String>,
+    /// Splits texts into the terms counted by the word frequency columns
+    tokenizer: Tokenizer,
+    /// Weights the word frequency columns, with document frequencies learned from training
+    tfidf: TfidfVectorizer,
+    /// Subreddit names, indexed by the float used as their ground truth label
+    labels: Vec<String>,
+    /// Lexicons whose hits and densities are emitted, in column order
+    lexicons: Vec<Lexicon>,
+}
+
+impl FeaturePipeline {
+    /// Learns the author table, label mapping and inverse document frequencies of `words` from
+    /// `raw_posts`. `words` is the vocabulary used for the word frequency columns, normalized by
+    /// the default `Tokenizer`.
+    pub fn fit(raw_posts: &[RawPostFeatures], words: Vec<String>) -> FeaturePipeline {
+        FeaturePipeline::fit_with_tokenizer(raw_posts, words, Tokenizer::default())
+    }
+
+    /// Like `fit`, with the words of both the vocabulary and the posts split by `tokenizer`.
+    /// Words that normalize to the same term share a column.
+    pub fn fit_with_tokenizer(raw_posts: &[RawPostFeatures],
+                              words: Vec<String>,
+                              tokenizer: Tokenizer)
+                              -> FeaturePipeline {
+        let mut author_popularity = BTreeMap::new();
+
+        for post in raw_posts.iter().filter(|p| p.subreddit == POSITIVE_SUB) {
+            author_popularity.insert(post.author.clone(), 0f32);
+        }
+
+        for post in raw_posts {
+            if let Some(f) = author_popularity.get_mut(&post.author) {
+                *f += 1f32;
+            }
+        }
+
+        let mut labels: Vec<String> = Vec::new();
+        for post in raw_posts {
+            if !labels.contains(&post.subreddit) {
+                labels.push(post.subreddit.clone());
+            }
+        }
+
+        let words = tokenizer.vocabulary(&words);
+        let terms = post_terms(raw_posts);
+        let terms: Vec<&str> = terms.iter().map(|s| s.as_str()).collect();
+        let counts = interesting_word_freq(&terms[..], &words[..], &tokenizer);
+        let tfidf = TfidfVectorizer::fit(&counts[..], words.len());
+
+        FeaturePipeline {
+            author_popularity: author_popularity,
+            words: words,
+            tokenizer: tokenizer,
+            tfidf: tfidf,
+            labels: labels,
+            lexicons: Vec::new(),
+        }
+    }
+
+    /// Appends the hits and density of every lexicon to the feature matrix
+    pub fn with_lexicons(mut self, lexicons: Vec<Lexicon>) -> FeaturePipeline {
+        self.lexicons = lexicons;
+        self
+    }
+
+    pub fn lexicons(&self) -> &[Lexicon] {
+        &self.lexicons
+    }
+
+    /// Sets how the word counts of a post are weighted before their inverse document frequency
+    /// is applied, and how the word columns of every row are normalized. Defaults to raw counts
+    /// and L2 normalization.
+    pub fn with_term_weighting(mut self, scheme: TfScheme, norm: Norm) -> FeaturePipeline {
+        self.tfidf = self.tfidf.scheme(scheme).norm(norm);
+        self
+    }
+
+    /// Fits the pipeline on `raw_posts` and returns it along with their feature matrix and
+    /// ground truth
+    pub fn fit_transform(raw_posts: &[RawPostFeatures],
+                         words: Vec<String>)
+                         -> (FeaturePipeline, Array, Array) {
+        let pipeline = FeaturePipeline::fit(raw_posts, words);
+        let features = pipeline.transform(raw_posts);
+        let targets = pipeline.targets(raw_posts);
+        (pipeline, features, targets)
+    }
+
+    pub fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    pub fn tokenizer(&self) -> &Tokenizer {
+        &self.tokenizer
+    }
+
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Returns the subreddit name for a predicted label
+    pub fn label_name(&self, label: f32) -> Option<&str> {
+        self.labels.get(label as usize).map(|s| s.as_str())
+    }
+
+    /// Returns the name of every column produced by `transform`, in column order.
+    ///
+    /// Word, symbol, regex, markdown and Rust syntax columns are prefixed with `word:`,
+    /// `symbol:`, `regex:`, `markdown:` and `rust:`. Lexicon columns are named after the lexicon
+    /// and its version, like `lexicon:game_items@1:hits`.
+    pub fn feature_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = BASE_COLUMNS.iter().map(|c| c.to_string()).collect();
+        names.extend(self.words.iter().map(|w| format!("word:{}", w)));
+        names.extend(SYMBOLS.iter().map(|s| format!("symbol:{}", s)));
+        names.extend(CODE_REGEX_NAMES.iter().map(|r| format!("regex:{}", r)));
+        names.extend(MARKDOWN_FEATURE_NAMES.iter().map(|m| format!("markdown:{}", m)));
+        names.extend(RUST_CONSTRUCT_NAMES.iter().map(|r| format!("rust:{}", r)));
+        for lexicon in &self.lexicons {
+            for column in &["hits", "density"] {
+                names.push(format!("lexicon:{}@{}:{}", lexicon.name, lexicon.version, column));
+            }
+        }
+        names
+    }
+
+    /// A stable hash of the feature matrix layout produced by this pipeline and of the extraction
+    /// code computing it. Besides the column names and labels it covers `FEATURE_SCHEMA_VERSION`,
+    /// which is compiled into the binary rather than stored with the pipeline. Models trained
+    /// with a different hash can not be applied to its output.
+    pub fn schema_hash(&self) -> u64 {
+        self.schema_hash_for(FEATURE_SCHEMA_VERSION)
+    }
+
+    /// The schema hash extraction code of `version` computes for this pipeline
+    pub fn schema_hash_for(&self, version: u32) -> u64 {
+        let mut hasher = FnvHasher::default();
+        version.hash(&mut hasher);
+        self.feature_names().hash(&mut hasher);
+        self.labels.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Returns the ground truth label of a subreddit, if the pipeline was fit on any of its posts
+    pub fn label_of(&self, subreddit: &str) -> Option<f32> {
+        self.labels.iter().position(|l| l == subreddit).map(|i| i as f32)
+    }
+
+    /// Maps every post's subreddit to its ground truth label. Panics if a post belongs to a
+    /// subreddit the pipeline was not fit on.
+    pub fn targets(&self, raw_posts: &[RawPostFeatures]) -> Array {
+        let targets: Vec<f32> = raw_posts.iter()
+                                         .map(|r| {
+                                             self.label_of(&r.subreddit)
+                                                 .expect("Post from a subreddit outside the \
+                                                          training set")
+                                         })
+                                         .collect();
+        Array::from(targets)
+    }
+
+    pub fn extract(&self, raw_posts: &[RawPostFeatures]) -> Vec<ProcessedPostFeatures> {
+        let posts: Vec<&str> = raw_posts.iter().map(|r| r.selftext.as_ref()).collect();
+
+        let terms = post_terms(raw_posts);
+        let terms: Vec<&str> = terms.iter().map(|s| s.as_str()).collect();
+
+        let term_counts = interesting_word_freq(&terms[..], &self.words[..], &self.tokenizer);
+        let term_frequencies = self.tfidf.transform(&term_counts[..]);
+        let lexicon_frequencies = lexicon_features(&terms[..],
+                                                   &self.lexicons,
+                                                   &self.tokenizer);
+        let symbol_frequencies = symbol_counts(&posts[..]);
+        let rust_regexes = check_for_code_in_markdown(&posts[..]);
+        let markdown = markdown_code_features(&posts[..]);
+        let rust_syntax = rust_syntax_features(&posts[..]);
+
+        raw_posts.iter()
+                 .enumerate()
+                 .map(|(index, r)| {
+                     ProcessedPostFeatures {
+                         is_self: convert_is_self(r.is_self),
+                         author_popularity: *self.author_popularity
+                                                 .get(&r.author)
+                                                 .unwrap_or(&0f32),
+                         downs: r.downs as f32,
+                         ups: r.ups as f32,
+                         score: r.score as f32,
+                         post_len: r.selftext.len() as f32,
+                         word_freq: term_frequencies[index].clone(),
+                         symbol_freq: symbol_frequencies[index].clone(),
+                         regex_matches: rust_regexes[index].clone(),
+                         markdown_features: markdown[index].clone(),
+                         rust_syntax: rust_syntax[index].clone(),
+                         lexicon_features: lexicon_frequencies[index].clone(),
+                     }
+                 })
+                 .collect()
+    }
+
+    /// Transforms `raw_posts` into a feature matrix with one row per post
+    pub fn transform(&self, raw_posts: &[RawPostFeatures]) -> Array {
+        construct_matrix(&self.extract(raw_posts)[..])
+    }
+}
+
+/// The selftext and title of every post, which the word and lexicon columns are computed on
+fn post_terms(raw_posts: &[RawPostFeatures]) -> Vec<String> {
+    raw_posts.iter()
+             .map(|r| {
+                 let mut comb = String::new();
+                 comb.push_str(&r.selftext);
+                 comb.push_str(" ");
+                 comb.push_str(&r.title);
+                 comb
+             })
+             .collect()
+}
+
+pub fn construct_matrix(post_features: &[ProcessedPostFeatures]) -> Array {
+    let feature_count = match post_features.first() {
+        Some(p) => {
+            5 + p.word_freq.len() + p.symbol_freq.len() + p.regex_matches.len() +
+            p.markdown_features.len() + p.rust_syntax.len() + p.lexicon_features.len()
+        }
+        None => 0,
+    };
+
+    let mut features = Vec::with_capacity(feature_count * post_features.len());
+
+    for p in post_features {
+        // Must stay in sync with BASE_COLUMNS
+        let row = vec![p.author_popularity, p.downs, p.ups, p.score, p.post_len];
+        features.extend_from_slice(&row[..]);
+        features.extend_from_slice(&p.word_freq[..]);
+        features.extend_from_slice(&p.symbol_freq[..]);
+        features.extend_from_slice(&p.regex_matches[..]);
+        features.extend_from_slice(&p.markdown_features[..]);
+        features.extend_from_slice(&p.rust_syntax[..]);
+        features.extend_from_slice(&p.lexicon_features[..]);
+    }
+
+    let mut features = Array::from(features);
+    features.reshape(post_features.len(), feature_count);
+    features
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reddit::RawPostFeatures;
+    use tokenizer::Tokenizer;
+    use vectorizer::{Norm, TfScheme};
+
+    use rustlearn::prelude::*;
+
+    fn post(author: &str, subreddit: &str, selftext: &str) -> RawPostFeatures {
+        RawPostFeatures {
+            is_self: true,
+            author: author.to_owned(),
+            url: String::new(),
+            downs: 0,
+            ups: 1,
+            score: 1,
+            selftext: selftext.to_owned(),
+            subreddit: subreddit.to_owned(),
+            title: "title".to_owned(),
+            ..RawPostFeatures::default()
+        }
+    }
+
+    #[test]
+    fn test_fit_transform_shape() {
+        let posts = vec![post("a", "rust", "fn main() {}"), post("b", "playrust", "wipe day")];
+        let words = vec!["main".to_owned(), "wipe".to_owned()];
+
+        let (pipeline, features, targets) = FeaturePipeline::fit_transform(&posts[..], words);
+
+        assert_eq!(features.rows(), 2);
+        assert_eq!(features.cols(), 5 + 2 + 26 + 4 + 3 + 10);
+        assert_eq!(features.cols(), pipeline.feature_names().len());
+        assert_eq!(targets.data(), &[0f32, 1f32][..]);
+        assert_eq!(pipeline.label_name(1f32), Some("playrust"));
+    }
+
+    #[test]
+    fn test_author_popularity_is_learned() {
+        let train = vec![post("a", "rust", ""), post("a", "playrust", ""), post("b", "playrust", "")];
+        let pipeline = FeaturePipeline::fit(&train[..], vec![]);
+
+        // The popularity of an author must not depend on the batch being transformed
+        let batch = vec![post("b", "playrust", ""), post("a", "rust", "")];
+        let features = pipeline.transform(&batch[..]);
+
+        assert_eq!(features.get(0, 0), 0f32);
+        assert_eq!(features.get(1, 0), 2f32);
+    }
+
+    #[test]
+    fn test_feature_names() {
+        let posts = vec![post("a", "rust", "")];
+        let pipeline = FeaturePipeline::fit(&posts[..], vec!["fence".to_owned()]);
+        let names = pipeline.feature_names();
+
+        assert_eq!(&names[..6],
+                   &["author_popularity", "downs", "ups", "score", "post_len", "word:fenc"]);
+        assert!(names.contains(&"symbol:;".to_owned()));
+        assert!(names.contains(&"regex:macro".to_owned()));
+        assert_eq!(names.last().map(|s| s.as_str()), Some("rust:parses"));
+    }
+
+    #[test]
+    fn test_words_are_normalized() {
+        let posts = vec![post("a", "rust", "Raided again"), post("b", "playrust", "")];
+        let words = vec!["raids".to_owned(), "Raiding".to_owned(), "the".to_owned()];
+        let pipeline = FeaturePipeline::fit(&posts[..], words);
+        assert_eq!(pipeline.words(), &["raid".to_owned()][..]);
+
+        let features = pipeline.transform(&posts[..]);
+        assert_eq!(features.get(0, 5), 1f32);
+        assert_eq!(features.get(1, 5), 0f32);
+
+        let exact = Tokenizer::default().stem(false);
+        let pipeline = FeaturePipeline::fit_with_tokenizer(&posts[..],
+                                                           vec!["raids".to_owned()],
+                                                           exact);
+        assert_eq!(pipeline.words(), &["raids".to_owned()][..]);
+        assert_eq!(pipeline.transform(&posts[..]).get(0, 5), 0f32);
+    }
+
+    #[test]
+    fn test_word_columns_are_tfidf_weighted() {
+        let posts = vec![post("a", "rust", "wipe wipe main"), post("b", "playrust", "wipe day")];
+        let words = vec!["main".to_owned(), "wipe".to_owned()];
+
+        let pipeline = FeaturePipeline::fit(&posts[..], words.clone());
+        let features = pipeline.transform(&posts[..]);
+        let (main, wipe) = (features.get(0, 5), features.get(0, 6));
+        assert!((main * main + wipe * wipe - 1f32).abs() < 1e-5);
+
+        // The inverse document frequencies are learned from the training posts only
+        let pipeline = FeaturePipeline::fit(&posts[..], words)
+                           .with_term_weighting(TfScheme::Raw, Norm::None);
+        let features = pipeline.transform(&posts[..1]);
+        assert!((features.get(0, 5) - (1.5f32.ln() + 1f32)).abs() < 1e-5);
+        assert_eq!(features.get(0, 6), 2f32);
+    }
+}
@@ -0,0 +1,193 @@
+use transport::Response;
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How `RedditClient` paces its requests and retries failed ones
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    /// Maximum number of requests sent within any `window`
+    pub requests_per_window: usize,
+    pub window: Duration,
+    /// Number of times a request is retried after a transient failure
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled on every further attempt
+    pub base_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RateLimit {
+    /// Reddit's documented budget for clients without OAuth credentials
+    fn default() -> RateLimit {
+        RateLimit {
+            requests_per_window: 30,
+            window: Duration::from_secs(60),
+            max_retries: 5,
+            base_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RateLimit {
+    /// No pacing and immediate retries, for tests and local mock servers
+    pub fn unlimited() -> RateLimit {
+        RateLimit {
+            requests_per_window: usize::max_value(),
+            window: Duration::from_secs(0),
+            max_retries: 5,
+            base_backoff: Duration::from_secs(0),
+            max_backoff: Duration::from_secs(0),
+        }
+    }
+}
+
+/// Whether a response with `status` is worth retrying
+pub fn is_transient(status: u16) -> bool {
+    match status {
+        429 | 500 | 502 | 503 | 504 => true,
+        _ => false,
+    }
+}
+
+fn duration_from_secs_f64(secs: f64) -> Duration {
+    let secs = secs.max(0.0);
+    Duration::new(secs.trunc() as u64, (secs.fract() * 1e9) as u32)
+}
+
+fn duration_as_secs_f64(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 / 1e9
+}
+
+/// Tracks sent requests and the budget reddit reports back through its `X-Ratelimit-*` headers
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RateLimit,
+    sent: VecDeque<Instant>,
+    /// Requests left in reddit's current period, and when that period resets
+    server_remaining: Option<f64>,
+    server_reset: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimit) -> RateLimiter {
+        RateLimiter {
+            config: config,
+            sent: VecDeque::new(),
+            server_remaining: None,
+            server_reset: None,
+        }
+    }
+
+    pub fn config(&self) -> &RateLimit {
+        &self.config
+    }
+
+    /// How long to wait at `now` before the next request may be sent
+    pub fn delay(&self, now: Instant) -> Duration {
+        let mut delay = Duration::from_secs(0);
+
+        if self.sent.len() >= self.config.requests_per_window {
+            if let Some(oldest) = self.sent.front() {
+                let frees_at = *oldest + self.config.window;
+                if frees_at > now {
+                    delay = frees_at.duration_since(now);
+                }
+            }
+        }
+
+        if let (Some(remaining), Some(reset)) = (self.server_remaining, self.server_reset) {
+            if remaining < 1.0 && reset > now {
+                delay = cmp::max(delay, reset.duration_since(now));
+            }
+        }
+
+        delay
+    }
+
+    pub fn record_request(&mut self, now: Instant) {
+        let window = self.config.window;
+        while self.sent.front().map_or(false, |sent| now.duration_since(*sent) >= window) {
+            self.sent.pop_front();
+        }
+        self.sent.push_back(now);
+    }
+
+    /// Reads `X-Ratelimit-Remaining` and `X-Ratelimit-Reset` from a response received at `now`
+    pub fn update_from_headers(&mut self, response: &Response, now: Instant) {
+        let remaining = response.header("X-Ratelimit-Remaining")
+                                .and_then(|r| r.trim().parse::<f64>().ok());
+        let reset = response.header("X-Ratelimit-Reset")
+                            .and_then(|r| r.trim().parse::<f64>().ok());
+
+        if let (Some(remaining), Some(reset)) = (remaining, reset) {
+            self.server_remaining = Some(remaining);
+            self.server_reset = Some(now + duration_from_secs_f64(reset));
+        }
+    }
+
+    /// The delay before retry number `attempt` (starting at 0). `jitter` in `[0, 1)` spreads
+    /// the delay over the upper half of the exponential backoff.
+    pub fn backoff(&self, attempt: u32, jitter: f64) -> Duration {
+        let base = duration_as_secs_f64(self.config.base_backoff);
+        let max = duration_as_secs_f64(self.config.max_backoff);
+
+        let exp = (base * 2f64.powi(cmp::min(attempt, 30) as i32)).min(max);
+        duration_from_secs_f64(exp * (0.5 + jitter.max(0.0).min(1.0) / 2.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use transport::Response;
+
+    use std::time::{Duration, Instant};
+
+    fn config() -> RateLimit {
+        RateLimit {
+            requests_per_window: 2,
+            window: Duration::from_secs(10),
+            ..RateLimit::default()
+        }
+    }
+
+    #[test]
+    fn test_window_budget() {
+        let mut limiter = RateLimiter::new(config());
+        let start = Instant::now();
+
+        limiter.record_request(start);
+        assert_eq!(limiter.delay(start), Duration::from_secs(0));
+
+        limiter.record_request(start + Duration::from_secs(4));
+        assert_eq!(limiter.delay(start + Duration::from_secs(4)),
+                   Duration::from_secs(6));
+        assert_eq!(limiter.delay(start + Duration::from_secs(10)),
+                   Duration::from_secs(0));
+    }
+
+    #[test]
+    fn test_server_headers() {
+        let mut limiter = RateLimiter::new(config());
+        let now = Instant::now();
+
+        let mut response = Response::ok(String::new());
+        response.headers.push(("x-ratelimit-remaining".to_owned(), "0.0".to_owned()));
+        response.headers.push(("x-ratelimit-reset".to_owned(), "42".to_owned()));
+        limiter.update_from_headers(&response, now);
+
+        assert_eq!(limiter.delay(now), Duration::from_secs(42));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let limiter = RateLimiter::new(config());
+
+        assert_eq!(limiter.backoff(0, 1.0), Duration::from_secs(1));
+        assert_eq!(limiter.backoff(3, 1.0), Duration::from_secs(8));
+        assert_eq!(limiter.backoff(3, 0.0), Duration::from_secs(4));
+        assert_eq!(limiter.backoff(20, 1.0), Duration::from_secs(60));
+    }
+}
@@ -1,11 +1,24 @@
-use hyper::Client;
+use rand;
 use rayon::prelude::*;
 use serde_json;
 use serde_json::Value;
-use std::io::prelude::*;
 use tiny_keccak::Keccak;
 
-#[derive(Deserialize, Debug, Clone, RustcEncodable, RustcDecodable)]
+use error::{Error, Result};
+use oauth::{OAuth, OAUTH_BASE_URL};
+use ratelimit::{RateLimit, RateLimiter, is_transient};
+use transport::{HyperTransport, Request, Response, Transport, form_encode, path_and_query};
+
+use std::cmp;
+use std::collections::HashSet;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A post as returned by reddit.
+///
+/// Fields after `title` were added later. They are defaulted when missing from the JSON, and
+/// CSVs written before they existed are read through `LegacyRawPostFeatures`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, RustcEncodable, RustcDecodable)]
 pub struct RawPostFeatures {
     pub is_self: bool,
     pub author: String,
@@ -16,6 +29,75 @@ pub struct RawPostFeatures {
     pub selftext: String,
     pub subreddit: String,
     pub title: String,
+    /// The base36 post id, e.g. `4tz6e5`
+    #[serde(default)]
+    pub id: String,
+    /// The fullname of the post, its id prefixed with `t3_`
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub permalink: String,
+    /// Seconds since the unix epoch
+    #[serde(default)]
+    pub created_utc: f64,
+    #[serde(default)]
+    pub num_comments: u64,
+    #[serde(default)]
+    pub link_flair_text: Option<String>,
+    #[serde(default)]
+    pub domain: String,
+    #[serde(default)]
+    pub over_18: bool,
+    #[serde(default)]
+    pub stickied: bool,
+    #[serde(default)]
+    pub upvote_ratio: f64,
+}
+
+impl RawPostFeatures {
+    /// The key posts are deduplicated by: the post id, or the title for posts scraped before
+    /// ids were recorded
+    pub fn dedup_key(&self) -> &str {
+        if self.id.is_empty() {
+            &self.title
+        } else {
+            &self.id
+        }
+    }
+}
+
+/// The columns of `RawPostFeatures` as written to CSV before post metadata was recorded
+#[derive(Debug, Clone, RustcDecodable)]
+pub struct LegacyRawPostFeatures {
+    pub is_self: bool,
+    pub author: String,
+    pub url: String,
+    pub downs: u64,
+    pub ups: u64,
+    pub score: u64,
+    pub selftext: String,
+    pub subreddit: String,
+    pub title: String,
+}
+
+/// Number of CSV columns written for a `LegacyRawPostFeatures`
+pub const LEGACY_COLUMNS: usize = 9;
+
+impl From<LegacyRawPostFeatures> for RawPostFeatures {
+    fn from(legacy: LegacyRawPostFeatures) -> RawPostFeatures {
+        RawPostFeatures {
+            is_self: legacy.is_self,
+            author: legacy.author,
+            url: legacy.url,
+            downs: legacy.downs,
+            ups: legacy.ups,
+            score: legacy.score,
+            selftext: legacy.selftext,
+            subreddit: legacy.subreddit,
+            title: legacy.title,
+            ..RawPostFeatures::default()
+        }
+    }
 }
 
 #[derive(Debug, Clone, RustcEncodable)]
@@ -32,108 +114,361 @@ pub struct ProcessedPostFeatures {
     pub score: f32,
     /// Length of the postcharacters
     pub post_len: f32,
-    /// Word frequency vector
+    /// TF-IDF weighted word frequency vector
     pub word_freq: Vec<f32>,
     /// symbol frequency vector
     pub symbol_freq: Vec<f32>,
-    /// Matches against regexes for rust code
+    /// Matches against regexes for rust code, inside the post's markdown code only
     pub regex_matches: Vec<f32>,
+    /// Code block and inline code counts and the fraction of lines inside code blocks
+    pub markdown_features: Vec<f32>,
+    /// Counts of Rust constructs in the post's code and whether any of it parses as Rust
+    pub rust_syntax: Vec<f32>,
+    /// Hits and density of every lexicon of the pipeline
+    pub lexicon_features: Vec<f32>,
 }
 
-pub struct RedditClient {
-    client: Client,
+/// The host `RedditClient` sends requests to unless configured otherwise
+pub const DEFAULT_BASE_URL: &'static str = "https://www.reddit.com";
+
+/// Identifies this crate to reddit, as its API rules require
+pub const DEFAULT_USER_AGENT: &'static str = concat!("rust:playrust_alert:v",
+                                                     env!("CARGO_PKG_VERSION"),
+                                                     " (+https://github.com/insanitybit/\
+                                                      PlayRustClassifier)");
+
+pub struct RedditClient<T: Transport = HyperTransport> {
+    transport: T,
+    base_url: String,
+    user_agent: String,
+    limiter: RateLimiter,
+    oauth: Option<OAuth>,
 }
 
-fn feature_from_value(value: &serde_json::Value) -> RawPostFeatures {
-    let obj = value.as_object().unwrap();
+/// Parses a single post, unwrapping the `data` envelope of a listing child if present
+pub fn parse_post(value: &serde_json::Value) -> Result<RawPostFeatures> {
+    let obj = try!(value.as_object().ok_or(Error::MissingField("data")));
 
     match obj.get("data") {
-        Some(ref data) => feature_from_value(data),
-        None => serde_json::from_value(value.clone()).unwrap(),
+        Some(ref data) => parse_post(data),
+        None => Ok(try!(serde_json::from_value(value.clone()))),
     }
 }
 
-pub fn get_posts(data: Vec<serde_json::Value>) -> Vec<RawPostFeatures> {
+pub fn get_posts(data: Vec<serde_json::Value>) -> Result<Vec<RawPostFeatures>> {
     let mut raw_features = Vec::with_capacity(data.len());
     data.par_iter()
-        .map(|data| feature_from_value(data))
+        .map(|data| parse_post(data))
         .collect_into(&mut raw_features);
-    raw_features
+    raw_features.into_iter().collect()
 }
 
-impl RedditClient {
-    pub fn new() -> RedditClient {
-        RedditClient { client: Client::new() }
+/// Walks `path` through nested objects of `value`, failing on the first missing key
+fn get_path<'a>(value: &'a Value, path: &[&'static str]) -> Result<&'a Value> {
+    let mut value = value;
+    for key in path {
+        value = try!(value.as_object()
+                          .and_then(|obj| obj.get(*key))
+                          .ok_or(Error::MissingField(*key)));
     }
+    Ok(value)
+}
 
+/// A reply to a post, or to another comment
+#[derive(Serialize, Deserialize, Debug, Clone, Default, RustcEncodable, RustcDecodable)]
+pub struct Comment {
+    pub id: String,
+    /// The fullname of the comment, its id prefixed with `t1_`
+    pub name: String,
+    /// The fullname of the post or comment this replies to
+    pub parent_id: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub body: String,
+    #[serde(default)]
+    pub score: i64,
+    #[serde(default)]
+    pub created_utc: f64,
+    /// Nesting level below the post, 0 for top level comments
+    #[serde(default)]
+    pub depth: u32,
+}
 
-    pub fn get_raw_features_from_url(&mut self, url: &str) -> Vec<serde_json::Value> {
-        let query = format!("{}.json", url);
+/// Number of "more" stub ids expanded per `/api/morechildren` request, reddit's maximum
+const MORE_CHILDREN_BATCH: usize = 100;
 
-        let mut res = self.client.get(&query).send().unwrap();
+/// Collects the comments of a listing depth first, along with the ids hidden behind its "more"
+/// stubs. Comments without replies carry an empty string instead of a listing, which is skipped.
+fn walk_comments(listing: &Value,
+                 depth: u32,
+                 comments: &mut Vec<Comment>,
+                 more: &mut Vec<String>)
+                 -> Result<()> {
+    let children = get_path(listing, &["data", "children"]).ok().and_then(|c| c.as_array());
+    if let Some(children) = children {
+        for child in children {
+            try!(walk_thing(child, depth, comments, more));
+        }
+    }
+    Ok(())
+}
 
-        let body = {
-            let mut s = String::new();
-            let _ = res.read_to_string(&mut s);
-            s
-        };
+fn walk_thing(thing: &Value,
+              depth: u32,
+              comments: &mut Vec<Comment>,
+              more: &mut Vec<String>)
+              -> Result<()> {
+    let kind = try!(get_path(thing, &["kind"]));
+    let data = try!(get_path(thing, &["data"]));
 
-        let data: Value = serde_json::from_str(&body).unwrap();
-        let data = data.as_array().expect("Expected array");
-        let data = data[0].as_object().expect("Data1 should have been an object");
-        let data = data.get("data").expect("Expected key data");
-        let data = data.as_object().expect("Data2 should have been an object");
+    match kind.as_str() {
+        Some("t1") => {
+            let mut comment: Comment = try!(serde_json::from_value(data.clone()));
+            if get_path(data, &["depth"]).is_err() {
+                comment.depth = depth;
+            }
+            comments.push(comment);
 
+            if let Ok(replies) = get_path(data, &["replies"]) {
+                try!(walk_comments(replies, depth + 1, comments, more));
+            }
+        }
+        Some("more") => {
+            if let Some(ids) = get_path(data, &["children"]).ok().and_then(|c| c.as_array()) {
+                more.extend(ids.iter().filter_map(|id| id.as_str()).map(String::from));
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
 
-        let data = data.get("children").expect("Expected children data");
-        let data = data.as_array().unwrap();
-        data.clone()
+impl RedditClient<HyperTransport> {
+    pub fn new() -> RedditClient<HyperTransport> {
+        RedditClient::with_transport(HyperTransport::new())
     }
+}
 
+impl<T: Transport> RedditClient<T> {
+    pub fn with_transport(transport: T) -> RedditClient<T> {
+        RedditClient {
+            transport: transport,
+            base_url: DEFAULT_BASE_URL.to_owned(),
+            user_agent: DEFAULT_USER_AGENT.to_owned(),
+            limiter: RateLimiter::new(RateLimit::default()),
+            oauth: None,
+        }
+    }
 
-    pub fn get_raw_features(&mut self,
-                            sub: &str,
-                            limit: u32,
-                            after: &Option<String>)
-                            -> (Vec<serde_json::Value>, Option<String>) {
-        let query = match *after {
-            Some(ref a) => {
-                format!("https://www.reddit.com/r/{}/new.json?sort=new&limit={}&after={}",
-                        sub,
-                        limit,
-                        a)
+    /// Sends every request to `base_url` instead of `DEFAULT_BASE_URL`
+    pub fn base_url(mut self, base_url: &str) -> RedditClient<T> {
+        self.base_url = base_url.trim_right_matches('/').to_owned();
+        self
+    }
+
+    /// Sets the User-Agent sent with every request. Reddit asks for a unique, descriptive
+    /// agent of the form `<platform>:<app ID>:<version> (by /u/<username>)`.
+    pub fn user_agent(mut self, user_agent: &str) -> RedditClient<T> {
+        self.user_agent = user_agent.to_owned();
+        self
+    }
+
+    pub fn rate_limit(mut self, rate_limit: RateLimit) -> RedditClient<T> {
+        self.limiter = RateLimiter::new(rate_limit);
+        self
+    }
+
+    /// Authenticates every request with a bearer token from `oauth`. Unless a custom base url
+    /// was configured, requests are routed to `OAUTH_BASE_URL`.
+    pub fn oauth(mut self, oauth: OAuth) -> RedditClient<T> {
+        if self.base_url == DEFAULT_BASE_URL {
+            self.base_url = OAUTH_BASE_URL.to_owned();
+        }
+        self.oauth = Some(oauth);
+        self
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Sends `request` within the rate limit, retrying transient failures with exponential
+    /// backoff. Responses that are still unsuccessful after the last retry are returned as is.
+    fn send(&mut self, request: Request) -> Result<Response> {
+        let request = request.header("User-Agent", &self.user_agent);
+        let max_retries = self.limiter.config().max_retries;
+        let mut attempt = 0;
+
+        loop {
+            let mut authorized = request.clone();
+            if let Some(ref mut oauth) = self.oauth {
+                let token = try!(oauth.bearer_token(&mut self.transport, &self.user_agent));
+                authorized = authorized.header("Authorization", &format!("bearer {}", token));
             }
-            None => {
-                format!("https://www.reddit.com/r/{}/new.json?sort=new&limit={}",
-                        sub,
-                        limit)
+
+            let delay = self.limiter.delay(Instant::now());
+            if delay > Duration::from_secs(0) {
+                thread::sleep(delay);
             }
-        };
 
-        let mut res = self.client.get(&query).send().unwrap();
+            self.limiter.record_request(Instant::now());
+            match self.transport.send(&authorized) {
+                // The token was revoked or expired early, retry once with a fresh one
+                Ok(ref res) if res.status == 401 && self.oauth.is_some() && attempt == 0 => {
+                    if let Some(ref mut oauth) = self.oauth {
+                        oauth.invalidate();
+                    }
+                    attempt += 1;
+                    continue;
+                }
+                Ok(res) => {
+                    self.limiter.update_from_headers(&res, Instant::now());
+                    if !is_transient(res.status) || attempt >= max_retries {
+                        return Ok(res);
+                    }
+                }
+                Err(Error::Http(_)) if attempt < max_retries => (),
+                Err(e) => return Err(e),
+            }
 
-        let body = {
-            let mut s = String::new();
-            let _ = res.read_to_string(&mut s);
-            s
-        };
+            thread::sleep(self.limiter.backoff(attempt, rand::random::<f64>()));
+            attempt += 1;
+        }
+    }
+
+    fn get_json(&mut self, path: &str) -> Result<Value> {
+        let url = format!("{}{}", self.base_url, path);
+        let res = try!(self.send(Request::get(&url)));
 
-        let data: Value = serde_json::from_str(&body).unwrap();
-        let data = data.as_object().unwrap();
-        let data = data.get("data").unwrap();
-        let data = data.as_object().unwrap();
-        let after = data.get("after").unwrap();
+        if !res.is_success() {
+            return Err(Error::Status(res.status));
+        }
 
-        let data = data.get("children").unwrap();
-        let data = data.as_array().unwrap();
+        Ok(try!(serde_json::from_str(&res.body)))
+    }
 
-        if after.is_string() {
-            (data.clone(), after.as_str().map(|s| s.to_owned()))
-        } else {
-            (data.clone(), None)
+    /// POSTs `params` form encoded to the API endpoint at `path`, e.g. `/api/report`, asking
+    /// for a JSON response. Errors reddit reports inside a successful response, like a missing
+    /// permission, are returned as `Error::Reddit`. Most endpoints that change anything require
+    /// an authenticated client.
+    pub fn post_form(&mut self, path: &str, params: &[(&str, &str)]) -> Result<Value> {
+        let url = format!("{}{}", self.base_url, path);
+        let mut params = params.to_vec();
+        params.push(("api_type", "json"));
+        let request = Request::post(&url, form_encode(&params))
+                          .header("Content-Type", "application/x-www-form-urlencoded");
+
+        let res = try!(self.send(request));
+        if !res.is_success() {
+            return Err(Error::Status(res.status));
+        }
+
+        if res.body.trim().is_empty() {
+            return Ok(Value::Object(Default::default()));
+        }
+        let data: Value = try!(serde_json::from_str(&res.body));
+        if let Some(errors) = get_path(&data, &["json", "errors"]).ok().and_then(|e| e.as_array()) {
+            if !errors.is_empty() {
+                return Err(Error::Reddit(serde_json::to_string(errors).unwrap_or(String::new())));
+            }
         }
+        Ok(data)
+    }
+
+    /// Fetches the listing of the post at `url`, which may be a full reddit url or a permalink.
+    /// Only the path is used, the request always goes to the configured base url.
+    pub fn get_raw_features_from_url(&mut self, url: &str) -> Result<Vec<serde_json::Value>> {
+        let query = format!("{}.json", path_and_query(url).trim_right_matches('/'));
 
+        let data = try!(self.get_json(&query));
+        let data = try!(data.as_array()
+                            .and_then(|listings| listings.first())
+                            .ok_or(Error::MissingField("listing")));
 
+        let data = try!(get_path(data, &["data", "children"]));
+        let data = try!(data.as_array().ok_or(Error::MissingField("children")));
+        Ok(data.clone())
+    }
+
+
+    /// Fetches every comment on the post at `url`, expanding "more" stubs through
+    /// `/api/morechildren`. Comments are returned depth first in the order reddit lists them,
+    /// followed by the expanded ones.
+    pub fn get_comments(&mut self, url: &str) -> Result<Vec<Comment>> {
+        let query = format!("{}.json", path_and_query(url).trim_right_matches('/'));
+
+        let data = try!(self.get_json(&query));
+        let listings = try!(data.as_array().ok_or(Error::MissingField("listing")));
+
+        let post = try!(listings.first().ok_or(Error::MissingField("listing")));
+        let post = try!(get_path(post, &["data", "children"]));
+        let post = try!(post.as_array()
+                            .and_then(|children| children.first())
+                            .ok_or(Error::MissingField("children")));
+        let link_id = try!(get_path(post, &["data", "name"]));
+        let link_id = try!(link_id.as_str().ok_or(Error::MissingField("name"))).to_owned();
+
+        let mut comments = Vec::new();
+        let mut more = Vec::new();
+        if let Some(listing) = listings.get(1) {
+            try!(walk_comments(listing, 0, &mut comments, &mut more));
+        }
+
+        // Expanded stubs may contain further stubs, never ask for the same id twice
+        let mut requested = HashSet::new();
+        while !more.is_empty() {
+            let batch_len = cmp::min(MORE_CHILDREN_BATCH, more.len());
+            let batch: Vec<String> = more.drain(..batch_len)
+                                         .filter(|id| requested.insert(id.clone()))
+                                         .collect();
+            if batch.is_empty() {
+                continue;
+            }
+
+            let query = format!("/api/morechildren.json?api_type=json&link_id={}&children={}",
+                                link_id,
+                                batch.join(","));
+            let data = try!(self.get_json(&query));
+            let things = try!(get_path(&data, &["json", "data", "things"]));
+            let things = try!(things.as_array().ok_or(Error::MissingField("things")));
+
+            for thing in things {
+                try!(walk_thing(thing, 0, &mut comments, &mut more));
+            }
+        }
+
+        Ok(comments)
+    }
+
+    pub fn get_raw_features(&mut self,
+                            sub: &str,
+                            limit: u32,
+                            after: &Option<String>)
+                            -> Result<(Vec<serde_json::Value>, Option<String>)> {
+        let query = match *after {
+            Some(ref a) => format!("/r/{}/new.json?sort=new&limit={}&after={}", sub, limit, a),
+            None => format!("/r/{}/new.json?sort=new&limit={}", sub, limit),
+        };
+
+        let data = try!(self.get_json(&query));
+        let data = try!(get_path(&data, &["data"]));
+        let after = try!(get_path(data, &["after"]));
+
+        let data = try!(get_path(data, &["children"]));
+        let data = try!(data.as_array().ok_or(Error::MissingField("children")));
+
+        Ok((data.clone(), after.as_str().map(|s| s.to_owned())))
+    }
+}
+
+/// Accepts either a post url or a post id, with or without its `t3_` prefix, and returns a url
+/// that `get_raw_features_from_url` can fetch
+pub fn post_url(url_or_id: &str) -> String {
+    if url_or_id.contains('/') {
+        url_or_id.to_owned()
+    } else {
+        format!("/comments/{}", url_or_id.trim_left_matches("t3_"))
     }
 }
 
@@ -159,6 +494,211 @@ pub fn anonymize_author(author: &str, iter: u64, key: &[u8]) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use error::Error;
+    use oauth::{Credentials, OAuth};
+    use serde_json;
+    use serde_json::Value;
+    use ratelimit::RateLimit;
+    use transport::{FixtureTransport, Method, Response};
+
+    fn fixture_client() -> RedditClient<FixtureTransport> {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/reddit");
+        RedditClient::with_transport(FixtureTransport::from_dir(dir))
+            .rate_limit(RateLimit::unlimited())
+    }
+
+    #[test]
+    fn test_pagination() {
+        let mut client = fixture_client();
+
+        let (first, after) = client.get_raw_features("rust", 2, &None).unwrap();
+        assert_eq!(first.len(), 2);
+        assert_eq!(after, Some("t3_4u0002".to_owned()));
+
+        let (second, after) = client.get_raw_features("rust", 2, &after).unwrap();
+        assert_eq!(second.len(), 1);
+        assert_eq!(after, None);
+
+        let posts = get_posts(second).unwrap();
+        assert_eq!(posts[0].title, "Announcing a new crate for parsing");
+        assert_eq!(posts[0].name, "t3_4u0001");
+        assert_eq!(posts[0].created_utc, 1469750400.0);
+        assert_eq!(posts[0].link_flair_text, None);
+        assert_eq!(client.transport().requests()[1].url,
+                   "https://www.reddit.com/r/rust/new.json?sort=new&limit=2&after=t3_4u0002");
+    }
+
+    #[test]
+    fn test_post_form() {
+        let rejected = r#"{"json": {"errors": [["USER_REQUIRED", "Please log in", null]]}}"#;
+        let mut client = fixture_client();
+        client.transport.insert(Method::Post,
+                                "/api/report",
+                                Response::ok(r#"{"json": {"errors": []}}"#.to_owned()));
+        client.transport.insert(Method::Post, "/api/remove", Response::ok(rejected.to_owned()));
+
+        client.post_form("/api/report", &[("thing_id", "t3_4u0002")]).unwrap();
+        assert_eq!(client.transport().requests()[0].body,
+                   Some("thing_id=t3_4u0002&api_type=json".to_owned()));
+
+        match client.post_form("/api/remove", &[("id", "t3_4u0002")]) {
+            Err(Error::Reddit(ref errors)) => assert!(errors.contains("USER_REQUIRED")),
+            other => panic!("Expected a reddit error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_base_url() {
+        let mut client = fixture_client().base_url("http://127.0.0.1:8080/");
+        let _ = client.get_raw_features("rust", 2, &None).unwrap();
+        assert_eq!(client.transport().requests()[0].url,
+                   "http://127.0.0.1:8080/r/rust/new.json?sort=new&limit=2");
+    }
+
+    #[test]
+    fn test_missing_listing() {
+        let mut client = fixture_client();
+        match client.get_raw_features("playrust", 2, &None) {
+            Err(Error::Status(404)) => (),
+            other => panic!("Expected a 404, got {:?}", other.map(|r| r.1)),
+        }
+    }
+
+    #[test]
+    fn test_malformed_listing() {
+        let mut transport = FixtureTransport::new();
+        transport.insert(Method::Get,
+                         "/r/rust/new.json?sort=new&limit=2",
+                         Response::ok(r#"{"data": {"after": null}}"#.to_owned()));
+        let mut client = RedditClient::with_transport(transport)
+                             .rate_limit(RateLimit::unlimited());
+
+        match client.get_raw_features("rust", 2, &None) {
+            Err(Error::MissingField("children")) => (),
+            other => panic!("Expected a missing field, got {:?}", other.map(|r| r.1)),
+        }
+    }
+
+    #[test]
+    fn test_retries_transient_failures() {
+        let path = "/r/rust/new.json?sort=new&limit=2";
+        let mut transport = FixtureTransport::new();
+        transport.insert(Method::Get,
+                         path,
+                         Response {
+                             status: 429,
+                             headers: Vec::new(),
+                             body: String::new(),
+                         });
+        transport.insert(Method::Get,
+                         path,
+                         Response::ok(r#"{"data": {"after": null, "children": []}}"#.to_owned()));
+
+        let mut client = RedditClient::with_transport(transport)
+                             .rate_limit(RateLimit::unlimited())
+                             .user_agent("test:agent:v1");
+        let (children, _) = client.get_raw_features("rust", 2, &None).unwrap();
+
+        assert!(children.is_empty());
+        let requests = client.transport().requests();
+        assert_eq!(requests.len(), 2);
+        let user_agent = ("User-Agent".to_owned(), "test:agent:v1".to_owned());
+        assert!(requests[1].headers.contains(&user_agent));
+    }
+
+    #[test]
+    fn test_gives_up_after_max_retries() {
+        let mut transport = FixtureTransport::new();
+        transport.insert(Method::Get,
+                         "/r/rust/new.json?sort=new&limit=2",
+                         Response {
+                             status: 503,
+                             headers: Vec::new(),
+                             body: String::new(),
+                         });
+
+        let rate_limit = RateLimit { max_retries: 2, ..RateLimit::unlimited() };
+        let mut client = RedditClient::with_transport(transport).rate_limit(rate_limit);
+
+        match client.get_raw_features("rust", 2, &None) {
+            Err(Error::Status(503)) => (),
+            other => panic!("Expected a 503, got {:?}", other.map(|r| r.1)),
+        }
+        assert_eq!(client.transport().requests().len(), 3);
+    }
+
+    #[test]
+    fn test_oauth() {
+        let token_url = "http://127.0.0.1:8080/api/v1/access_token";
+        let mut transport = FixtureTransport::new();
+        transport.insert(Method::Post,
+                         token_url,
+                         Response::ok(r#"{"access_token": "abc", "expires_in": 3600}"#
+                                          .to_owned()));
+        transport.insert(Method::Get,
+                         "/r/rust/new.json?sort=new&limit=2",
+                         Response::ok(r#"{"data": {"after": null, "children": []}}"#.to_owned()));
+
+        let credentials = Credentials {
+            client_id: "id".to_owned(),
+            client_secret: "secret".to_owned(),
+            username: None,
+            password: None,
+        };
+        let mut client = RedditClient::with_transport(transport)
+                             .rate_limit(RateLimit::unlimited())
+                             .oauth(OAuth::new(credentials).token_url(token_url));
+
+        client.get_raw_features("rust", 2, &None).unwrap();
+        client.get_raw_features("rust", 2, &None).unwrap();
+
+        let requests = client.transport().requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].url, token_url);
+        assert_eq!(requests[1].url,
+                   "https://oauth.reddit.com/r/rust/new.json?sort=new&limit=2");
+        let authorization = ("Authorization".to_owned(), "bearer abc".to_owned());
+        assert!(requests[1].headers.contains(&authorization));
+        assert!(requests[2].headers.contains(&authorization));
+    }
+
+    #[test]
+    fn test_parse_post_defaults_missing_metadata() {
+        let value: Value = serde_json::from_str(r#"{"kind": "t3", "data": {
+            "is_self": true, "author": "a", "url": "u", "downs": 0, "ups": 1, "score": 1,
+            "selftext": "text", "subreddit": "rust", "title": "title"}}"#)
+                               .unwrap();
+        let post = parse_post(&value).unwrap();
+
+        assert_eq!(post.id, "");
+        assert_eq!(post.dedup_key(), "title");
+        assert_eq!(post.num_comments, 0);
+    }
+
+    #[test]
+    fn test_get_comments() {
+        let mut client = fixture_client();
+        let comments = client.get_comments("https://www.reddit.com/r/rust/comments/4u0003/post/")
+                             .unwrap();
+
+        let ids: Vec<&str> = comments.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, vec!["c1", "c2", "c3", "c4", "c5"]);
+
+        let depths: Vec<u32> = comments.iter().map(|c| c.depth).collect();
+        assert_eq!(depths, vec![0, 1, 0, 0, 1]);
+
+        let requests = client.transport().requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].url.ends_with("link_id=t3_4u0003&children=c4,c5"));
+    }
+
+    #[test]
+    fn test_post_url() {
+        assert_eq!(post_url("t3_4u0003"), "/comments/4u0003");
+        assert_eq!(post_url("4u0003"), "/comments/4u0003");
+        assert_eq!(post_url("https://www.reddit.com/r/rust/comments/4u0003/post/"),
+                   "https://www.reddit.com/r/rust/comments/4u0003/post/");
+    }
 
     #[test]
     fn test_anon() {
@@ -0,0 +1,326 @@
+/// A token of Rust source code. Keywords are lexed as identifiers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Ident(String),
+    /// A lifetime or label, without its leading quote
+    Lifetime(String),
+    /// A string, character or number literal
+    Literal,
+    /// An operator or delimiter, with the multi character operators kept together
+    Punct(String),
+    /// A character that can not appear in Rust outside of literals and comments, or an
+    /// unterminated literal or comment
+    Unknown(char),
+}
+
+/// Operators of more than one character, longest first so `..=` wins over `..`
+const MULTI_CHAR_PUNCT: [&'static str; 19] = ["...", "..=", "<<=", ">>=", "::", "->", "=>", "==",
+                                              "!=", "<=", ">=", "&&", "||", "+=", "-=", "*=",
+                                              "/=", "..", "<<"];
+
+const SINGLE_CHAR_PUNCT: &'static str = "+-*/%^!&|=<>@.,;:#$?~()[]{}";
+
+/// Keywords that can start an item or statement
+const ITEM_KEYWORDS: [&'static str; 14] = ["fn", "struct", "enum", "impl", "trait", "use", "mod",
+                                           "pub", "let", "const", "static", "type", "extern",
+                                           "macro_rules"];
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_ident_continue(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn starts_with(chars: &[char], i: usize, s: &str) -> bool {
+    s.chars().enumerate().all(|(n, c)| chars.get(i + n) == Some(&c))
+}
+
+/// If a raw string like `r#"..."#` or `br"..."` starts at `i`, returns the index of its first
+/// `#` and the number of them
+fn raw_string_start(chars: &[char], i: usize) -> Option<(usize, usize)> {
+    let start = match (chars.get(i), chars.get(i + 1)) {
+        (Some(&'r'), _) => i + 1,
+        (Some(&'b'), Some(&'r')) => i + 2,
+        _ => return None,
+    };
+    let hashes = chars[start..].iter().take_while(|&&h| h == '#').count();
+    if chars.get(start + hashes) == Some(&'"') {
+        Some((start, hashes))
+    } else {
+        None
+    }
+}
+
+/// Splits `code` into tokens, skipping whitespace and comments. Lexing never fails: anything
+/// that is not Rust becomes a `Token::Unknown`, so snippets can still be counted.
+pub fn tokenize(code: &str) -> Vec<Token> {
+    let chars: Vec<char> = code.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+        } else if starts_with(&chars, i, "//") {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if starts_with(&chars, i, "/*") {
+            // Block comments nest
+            let mut depth = 0;
+            loop {
+                if i >= chars.len() {
+                    tokens.push(Token::Unknown('/'));
+                    break;
+                } else if starts_with(&chars, i, "/*") {
+                    depth += 1;
+                    i += 2;
+                } else if starts_with(&chars, i, "*/") {
+                    depth -= 1;
+                    i += 2;
+                    if depth == 0 {
+                        break;
+                    }
+                } else {
+                    i += 1;
+                }
+            }
+        } else if c == '"' || starts_with(&chars, i, "b\"") {
+            i += if c == '"' { 1 } else { 2 };
+            while i < chars.len() && chars[i] != '"' {
+                i += if chars[i] == '\\' { 2 } else { 1 };
+            }
+            tokens.push(if i < chars.len() {
+                Token::Literal
+            } else {
+                Token::Unknown('"')
+            });
+            i += 1;
+        } else if let Some((start, hashes)) = raw_string_start(&chars, i) {
+            let terminator: String = Some('"').into_iter()
+                                              .chain((0..hashes).map(|_| '#'))
+                                              .collect();
+            i = start + hashes + 1;
+            while i < chars.len() && !starts_with(&chars, i, &terminator) {
+                i += 1;
+            }
+            tokens.push(if i < chars.len() {
+                Token::Literal
+            } else {
+                Token::Unknown('"')
+            });
+            i += terminator.len();
+        } else if c == '\'' {
+            // `'a'` and `'\n'` are characters, `'a` is a lifetime
+            let ident_len = chars[i + 1..].iter().take_while(|&&c| is_ident_continue(c)).count();
+            let is_lifetime = chars.get(i + 1).map_or(false, |&c| is_ident_start(c)) &&
+                              chars.get(i + 1 + ident_len) != Some(&'\'');
+            if is_lifetime {
+                let name = chars[i + 1..i + 1 + ident_len].iter().cloned().collect();
+                tokens.push(Token::Lifetime(name));
+                i += 1 + ident_len;
+            } else {
+                i += 1;
+                while i < chars.len() && chars[i] != '\'' && chars[i] != '\n' {
+                    i += if chars[i] == '\\' { 2 } else { 1 };
+                }
+                tokens.push(if chars.get(i) == Some(&'\'') {
+                    Token::Literal
+                } else {
+                    Token::Unknown('\'')
+                });
+                i += 1;
+            }
+        } else if c.is_digit(10) {
+            i += 1;
+            while i < chars.len() &&
+                  (is_ident_continue(chars[i]) ||
+                   chars[i] == '.' && chars.get(i + 1).map_or(false, |c| c.is_digit(10))) {
+                i += 1;
+            }
+            tokens.push(Token::Literal);
+        } else if is_ident_start(c) {
+            let start = i;
+            while i < chars.len() && is_ident_continue(chars[i]) {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().cloned().collect()));
+        } else if let Some(punct) = MULTI_CHAR_PUNCT.iter().find(|p| starts_with(&chars, i, **p)) {
+            tokens.push(Token::Punct(punct.to_string()));
+            i += punct.len();
+        } else if SINGLE_CHAR_PUNCT.contains(c) {
+            tokens.push(Token::Punct(c.to_string()));
+            i += 1;
+        } else {
+            tokens.push(Token::Unknown(c));
+            i += 1;
+        }
+    }
+
+    tokens
+}
+
+fn is_ident(token: Option<&Token>, name: &str) -> bool {
+    match token {
+        Some(&Token::Ident(ref ident)) => ident == name,
+        _ => false,
+    }
+}
+
+fn is_punct(token: Option<&Token>, punct: &str) -> bool {
+    match token {
+        Some(&Token::Punct(ref p)) => p == punct,
+        _ => false,
+    }
+}
+
+/// Names of the columns produced by `rust_construct_counts`, in column order
+pub const RUST_CONSTRUCT_NAMES: [&'static str; 10] = ["lifetimes",
+                                                      "paths",
+                                                      "impl",
+                                                      "trait",
+                                                      "where",
+                                                      "mut_refs",
+                                                      "result_option",
+                                                      "derives",
+                                                      "unsafe",
+                                                      "parses"];
+
+/// Counts the constructs named by `RUST_CONSTRUCT_NAMES` among `tokens`. The last column is 1
+/// if `looks_like_rust` holds for them.
+pub fn rust_construct_counts(tokens: &[Token]) -> Vec<f32> {
+    let mut counts = vec![0f32; RUST_CONSTRUCT_NAMES.len()];
+
+    for (i, token) in tokens.iter().enumerate() {
+        let next = tokens.get(i + 1);
+        let column = match *token {
+            Token::Lifetime(_) => Some(0),
+            Token::Punct(ref p) if p == "::" => Some(1),
+            Token::Ident(ref ident) if ident == "impl" => Some(2),
+            Token::Ident(ref ident) if ident == "trait" => Some(3),
+            Token::Ident(ref ident) if ident == "where" => Some(4),
+            Token::Punct(ref p) if p == "&" && is_ident(next, "mut") => Some(5),
+            Token::Ident(ref ident) if (ident == "Result" || ident == "Option") &&
+                                       is_punct(next, "<") => Some(6),
+            Token::Punct(ref p) if p == "#" && is_punct(next, "[") &&
+                                   is_ident(tokens.get(i + 2), "derive") => Some(7),
+            Token::Ident(ref ident) if ident == "unsafe" => Some(8),
+            _ => None,
+        };
+        if let Some(column) = column {
+            counts[column] += 1.0;
+        }
+    }
+
+    counts[9] = if looks_like_rust(tokens) { 1.0 } else { 0.0 };
+    counts
+}
+
+/// A cheap stand in for parsing `tokens` as a sequence of Rust items or as an expression: every
+/// token is valid Rust, the delimiters are balanced, and the snippet either starts like an item
+/// or statement, or is a single expression like `foo.bar(x)?`.
+pub fn looks_like_rust(tokens: &[Token]) -> bool {
+    if tokens.is_empty() {
+        return false;
+    }
+
+    let mut open = Vec::new();
+    for token in tokens {
+        match *token {
+            Token::Unknown(_) => return false,
+            Token::Punct(ref p) => {
+                match p.as_str() {
+                    "(" | "[" | "{" => open.push(p.clone()),
+                    ")" | "]" | "}" => {
+                        let expected = match p.as_str() {
+                            ")" => "(",
+                            "]" => "[",
+                            _ => "{",
+                        };
+                        if open.pop().as_ref().map(|o| o.as_str()) != Some(expected) {
+                            return false;
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+    }
+    if !open.is_empty() {
+        return false;
+    }
+
+    let starts_like_item = match tokens[0] {
+        Token::Ident(ref ident) => ITEM_KEYWORDS.contains(&ident.as_str()),
+        Token::Punct(ref p) => p == "#",
+        _ => false,
+    };
+    if starts_like_item {
+        return true;
+    }
+
+    // An expression: operands and operators alternate sensibly, which prose rarely does. Two
+    // identifiers in a row, like `the code`, are only valid Rust in a few places.
+    let words_in_a_row = tokens.windows(2).any(|pair| match (&pair[0], &pair[1]) {
+        (&Token::Ident(_), &Token::Ident(_)) => true,
+        _ => false,
+    });
+    let has_operator = tokens.iter().any(|t| match *t {
+        Token::Punct(ref p) => p != "," && p != ".",
+        _ => false,
+    });
+    !words_in_a_row && has_operator
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lifetimes_and_chars() {
+        let tokens = tokenize("fn f<'a>(x: &'a str) -> char { 'x' }");
+        let lifetimes: Vec<&Token> = tokens.iter()
+                                           .filter(|t| match **t {
+                                               Token::Lifetime(_) => true,
+                                               _ => false,
+                                           })
+                                           .collect();
+        assert_eq!(lifetimes, vec![&Token::Lifetime("a".to_owned()); 2]);
+        assert!(tokens.contains(&Token::Literal));
+        assert!(tokens.contains(&Token::Punct("->".to_owned())));
+    }
+
+    #[test]
+    fn test_comments_and_strings_are_skipped() {
+        let tokens = tokenize("// impl Foo\n/* unsafe /* nested */ */ let s = r#\"where\"#;");
+        assert_eq!(tokens,
+                   vec![Token::Ident("let".to_owned()),
+                        Token::Ident("s".to_owned()),
+                        Token::Punct("=".to_owned()),
+                        Token::Literal,
+                        Token::Punct(";".to_owned())]);
+    }
+
+    #[test]
+    fn test_construct_counts() {
+        let code = "#[derive(Debug)]\nstruct S;\nimpl<'a> Tr for &'a S where S: Clone {\n    fn \
+                    f(&mut self) -> Result<Option<u8>, ()> { unsafe { std::mem::zeroed() } }\n}";
+        let counts = rust_construct_counts(&tokenize(code));
+        assert_eq!(counts,
+                   vec![2f32, 2f32, 1f32, 0f32, 1f32, 1f32, 2f32, 1f32, 1f32, 1f32]);
+    }
+
+    #[test]
+    fn test_looks_like_rust() {
+        assert!(looks_like_rust(&tokenize("let v: Vec<u8> = Vec::new();")));
+        assert!(looks_like_rust(&tokenize("foo.bar(x)?")));
+        assert!(!looks_like_rust(&tokenize("fn main() {")));
+        assert!(!looks_like_rust(&tokenize("my base got raided (again)")));
+        assert!(!looks_like_rust(&tokenize("")));
+    }
+}
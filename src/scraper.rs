@@ -0,0 +1,149 @@
+use error::Result;
+use reddit::{RawPostFeatures, RedditClient, parse_post};
+use store::{Checkpoint, PostStore};
+use transport::Transport;
+
+use serde_json::Value;
+
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScrapeStats {
+    /// Number of listing pages requested
+    pub pages: usize,
+    /// Number of posts that were not in the store yet
+    pub inserted: usize,
+    /// Number of posts that could not be parsed
+    pub malformed: usize,
+}
+
+fn parse_page(page: Vec<Value>, stats: &mut ScrapeStats) -> Vec<RawPostFeatures> {
+    page.iter()
+        .filter_map(|value| {
+            match parse_post(value) {
+                Ok(post) => Some(post),
+                Err(e) => {
+                    let _ = writeln!(io::stderr(), "skipping malformed post: {}", e);
+                    stats.malformed += 1;
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Scrapes `/r/{sub}/new` into `store`, picking up where the previous scrape left off.
+///
+/// First the posts created since the newest stored one are fetched, paging from the top of the
+/// listing until a stored post is reached. They are only written once that post was found, so
+/// an interrupted catch up is simply repeated. Then, until reddit's listing is exhausted, the
+/// backfill of older posts continues from the checkpointed `after` cursor, which is saved after
+/// every page.
+pub fn scrape<T: Transport>(client: &mut RedditClient<T>,
+                            store: &mut PostStore,
+                            sub: &str,
+                            page_size: u32)
+                            -> Result<ScrapeStats> {
+    let mut stats = ScrapeStats::default();
+
+    if !store.is_empty() {
+        let mut newer = Vec::new();
+        let mut after = None;
+
+        loop {
+            let (page, next) = try!(client.get_raw_features(sub, page_size, &after));
+            stats.pages += 1;
+
+            let posts = parse_page(page, &mut stats);
+            let reached_stored = posts.iter().any(|post| store.contains(&post.id));
+            newer.extend(posts.into_iter().filter(|post| !store.contains(&post.id)));
+
+            if reached_stored || next.is_none() {
+                break;
+            }
+            after = next;
+        }
+
+        // Oldest first, the order they would have been stored in had they been scraped live
+        for post in newer.into_iter().rev() {
+            if try!(store.insert(post)) {
+                stats.inserted += 1;
+            }
+        }
+    }
+
+    if !store.checkpoint().backfill_complete {
+        let mut after = store.checkpoint().after.clone();
+
+        loop {
+            let (page, next) = try!(client.get_raw_features(sub, page_size, &after));
+            stats.pages += 1;
+
+            for post in parse_page(page, &mut stats) {
+                if try!(store.insert(post)) {
+                    stats.inserted += 1;
+                }
+            }
+
+            after = next;
+            try!(store.set_checkpoint(Checkpoint {
+                after: after.clone(),
+                backfill_complete: after.is_none(),
+            }));
+
+            if after.is_none() {
+                break;
+            }
+        }
+    }
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratelimit::RateLimit;
+    use reddit::RedditClient;
+    use store::{Checkpoint, PostStore};
+    use transport::FixtureTransport;
+    use util::temp_dir;
+
+    fn fixture_client() -> RedditClient<FixtureTransport> {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/reddit");
+        RedditClient::with_transport(FixtureTransport::from_dir(dir))
+            .rate_limit(RateLimit::unlimited())
+    }
+
+    #[test]
+    fn test_backfill_then_incremental() {
+        let mut store = PostStore::open(temp_dir("scrape_incremental")).unwrap();
+
+        let stats = scrape(&mut fixture_client(), &mut store, "rust", 2).unwrap();
+        assert_eq!(stats.pages, 2);
+        assert_eq!(stats.inserted, 3);
+        assert!(store.checkpoint().backfill_complete);
+
+        // Nothing new was posted, the first page already contains stored posts
+        let stats = scrape(&mut fixture_client(), &mut store, "rust", 2).unwrap();
+        assert_eq!(stats.pages, 1);
+        assert_eq!(stats.inserted, 0);
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint() {
+        let mut store = PostStore::open(temp_dir("scrape_resume")).unwrap();
+        store.set_checkpoint(Checkpoint {
+                 after: Some("t3_4u0002".to_owned()),
+                 backfill_complete: false,
+             })
+             .unwrap();
+
+        let mut client = fixture_client();
+        let stats = scrape(&mut client, &mut store, "rust", 2).unwrap();
+
+        assert_eq!(stats.pages, 1);
+        assert!(store.contains("4u0001"));
+        assert!(client.transport().requests()[0].url.ends_with("after=t3_4u0002"));
+    }
+}
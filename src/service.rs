@@ -0,0 +1,316 @@
+use serde_json::{self, Value};
+
+use error::{Error, Result};
+use model::{ModelBundle, Outcome};
+use reddit::{RawPostFeatures, RedditClient, get_posts, parse_post, post_url};
+use transport::Transport;
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// A response of the classification service, independent of the HTTP server it is sent with
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceResponse {
+    pub status: u16,
+    /// A JSON document
+    pub body: String,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+impl ServiceResponse {
+    fn json<S: ::serde::Serialize>(status: u16, value: &S) -> ServiceResponse {
+        match serde_json::to_string(value) {
+            Ok(body) => {
+                ServiceResponse {
+                    status: status,
+                    body: body,
+                }
+            }
+            Err(e) => ServiceResponse::error(500, &e.to_string()),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> ServiceResponse {
+        let body = ErrorBody { error: message.to_owned() };
+        ServiceResponse {
+            status: status,
+            body: serde_json::to_string(&body).unwrap_or(String::new()),
+        }
+    }
+}
+
+/// The classification of one post
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Classification {
+    pub id: String,
+    /// The predicted subreddit, or `abstain`
+    pub outcome: String,
+    /// The most probable subreddit, even when abstaining
+    pub label: String,
+    pub probability: f32,
+}
+
+#[derive(Serialize)]
+struct BatchItem {
+    #[serde(skip_serializing_if="Option::is_none")]
+    result: Option<Classification>,
+    #[serde(skip_serializing_if="Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ModelInfo {
+    version: u32,
+    schema_hash: String,
+    labels: Vec<String>,
+    thresholds: BTreeMap<String, f32>,
+    trained_at: u64,
+    training_posts: usize,
+    trees: usize,
+    cv_accuracy: f32,
+}
+
+#[derive(Serialize)]
+struct Health {
+    status: &'static str,
+}
+
+/// Routes classification requests to a model bundle loaded once at startup.
+///
+/// `handle` takes `&self` so a single service can answer requests from many threads, only
+/// fetching posts from reddit is serialized, to respect the client's rate limit.
+pub struct Service<T: Transport> {
+    bundle: ModelBundle,
+    client: Mutex<RedditClient<T>>,
+}
+
+impl<T: Transport> Service<T> {
+    pub fn new(bundle: ModelBundle, client: RedditClient<T>) -> Service<T> {
+        Service {
+            bundle: bundle,
+            client: Mutex::new(client),
+        }
+    }
+
+    /// Answers a request. `path` may include a query string, which is ignored.
+    ///
+    /// * `GET /health`
+    /// * `GET /model`, the bundle's version and training metadata
+    /// * `POST /classify`, a post to classify, see `resolve_post`
+    /// * `POST /classify/batch`, a JSON array of posts to classify
+    pub fn handle(&self, method: &str, path: &str, body: &str) -> ServiceResponse {
+        let path = path.split('?').next().unwrap_or("");
+
+        match (method, path) {
+            ("GET", "/health") => ServiceResponse::json(200, &Health { status: "ok" }),
+            ("GET", "/model") => ServiceResponse::json(200, &self.model_info()),
+            ("POST", "/classify") => {
+                match self.classify_one(body) {
+                    Ok(classification) => ServiceResponse::json(200, &classification),
+                    Err(e) => ServiceResponse::error(status_for(&e), &e.to_string()),
+                }
+            }
+            ("POST", "/classify/batch") => {
+                match self.classify_batch(body) {
+                    Ok(items) => ServiceResponse::json(200, &items),
+                    Err(e) => ServiceResponse::error(status_for(&e), &e.to_string()),
+                }
+            }
+            (_, "/health") | (_, "/model") | (_, "/classify") | (_, "/classify/batch") => {
+                ServiceResponse::error(405, "method not allowed")
+            }
+            _ => ServiceResponse::error(404, "not found"),
+        }
+    }
+
+    fn model_info(&self) -> ModelInfo {
+        let labels = self.bundle.pipeline.labels().to_vec();
+        let metadata = &self.bundle.metadata;
+        ModelInfo {
+            version: self.bundle.version,
+            schema_hash: format!("{:016x}", self.bundle.schema_hash),
+            thresholds: labels.iter().cloned().zip(self.bundle.thresholds.clone()).collect(),
+            labels: labels,
+            trained_at: metadata.trained_at,
+            training_posts: metadata.training_posts,
+            trees: metadata.trees,
+            cv_accuracy: metadata.cv_accuracy,
+        }
+    }
+
+    /// Turns a request item into a post: a JSON string or an object with a `url` field is a
+    /// post url or id fetched from reddit, any other object is the post itself, with or without
+    /// the `data` envelope of a listing child.
+    fn resolve_post(&self, value: &Value) -> Result<RawPostFeatures> {
+        let url = match *value {
+            Value::String(ref url) => Some(url.clone()),
+            Value::Object(ref obj) => obj.get("url").and_then(|u| u.as_str()).map(String::from),
+            _ => None,
+        };
+
+        match url {
+            Some(ref url) if value.as_object().map_or(true, |obj| obj.len() == 1) => {
+                let mut client = self.client.lock().unwrap_or_else(|e| e.into_inner());
+                let raw = try!(client.get_raw_features_from_url(&post_url(url)));
+                let mut posts = try!(get_posts(raw));
+                if posts.is_empty() {
+                    return Err(Error::MissingField("children"));
+                }
+                Ok(posts.remove(0))
+            }
+            _ => parse_post(value),
+        }
+    }
+
+    fn classify(&self, posts: &[RawPostFeatures]) -> Result<Vec<Classification>> {
+        let predictions = try!(self.bundle.classify(posts));
+        Ok(posts.iter()
+                .zip(predictions)
+                .map(|(post, prediction)| {
+                    Classification {
+                        id: post.dedup_key().to_owned(),
+                        outcome: match prediction.outcome {
+                            Outcome::Label(ref label) => label.clone(),
+                            Outcome::Abstain => "abstain".to_owned(),
+                        },
+                        label: prediction.label,
+                        probability: prediction.probability,
+                    }
+                })
+                .collect())
+    }
+
+    fn classify_one(&self, body: &str) -> Result<Classification> {
+        let value = parse_body(body);
+        let post = try!(self.resolve_post(&value));
+        let mut classifications = try!(self.classify(&[post]));
+        Ok(classifications.remove(0))
+    }
+
+    fn classify_batch(&self, body: &str) -> Result<Vec<BatchItem>> {
+        let values: Vec<Value> = try!(serde_json::from_str(body));
+
+        // Items that can not be resolved get an error, the rest are classified together
+        let resolved: Vec<Result<RawPostFeatures>> = values.iter()
+                                                           .map(|v| self.resolve_post(v))
+                                                           .collect();
+        let posts: Vec<RawPostFeatures> = resolved.iter()
+                                                  .filter_map(|r| r.as_ref().ok().cloned())
+                                                  .collect();
+        let mut classifications = try!(self.classify(&posts)).into_iter();
+
+        Ok(resolved.into_iter()
+                   .map(|r| {
+                       match r {
+                           Ok(_) => {
+                               BatchItem {
+                                   result: classifications.next(),
+                                   error: None,
+                               }
+                           }
+                           Err(e) => {
+                               BatchItem {
+                                   result: None,
+                                   error: Some(e.to_string()),
+                               }
+                           }
+                       }
+                   })
+                   .collect())
+    }
+}
+
+/// A body that is not JSON is taken to be a bare post url or id
+fn parse_body(body: &str) -> Value {
+    serde_json::from_str(body).unwrap_or_else(|_| Value::String(body.trim().to_owned()))
+}
+
+/// Bad input is the client's fault, failing to reach reddit or to apply the model is ours
+fn status_for(error: &Error) -> u16 {
+    match *error {
+        Error::Json(_) | Error::MissingField(_) => 400,
+        Error::Status(404) => 404,
+        Error::Http(_) | Error::Status(_) | Error::Auth(_) | Error::Reddit(_) => 502,
+        _ => 500,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::test_bundle;
+    use ratelimit::RateLimit;
+    use reddit::RedditClient;
+    use transport::FixtureTransport;
+
+    use serde_json::{self, Value};
+
+    fn service() -> Service<FixtureTransport> {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/reddit");
+        let client = RedditClient::with_transport(FixtureTransport::from_dir(dir))
+                         .rate_limit(RateLimit::unlimited());
+        Service::new(test_bundle(), client)
+    }
+
+    fn json(response: &ServiceResponse) -> Value {
+        serde_json::from_str(&response.body).unwrap()
+    }
+
+    #[test]
+    fn test_health_and_model() {
+        let service = service();
+        assert_eq!(service.handle("GET", "/health", "").status, 200);
+
+        let response = service.handle("GET", "/model", "");
+        assert_eq!(response.status, 200);
+        assert_eq!(json(&response).find("trees").and_then(|t| t.as_u64()), Some(2));
+    }
+
+    #[test]
+    fn test_classify_post_json() {
+        let body = r#"{"kind": "t3", "data": {"id": "x1", "is_self": true, "author": "a",
+                       "url": "", "downs": 0, "ups": 1, "score": 1, "selftext": "wipe",
+                       "subreddit": "playrust", "title": "t"}}"#;
+        let response = service().handle("POST", "/classify", body);
+        assert_eq!(response.status, 200);
+
+        let result = json(&response);
+        assert_eq!(result.find("id").and_then(|i| i.as_str()), Some("x1"));
+        assert!(result.find("probability").and_then(|p| p.as_f64()).is_some());
+    }
+
+    #[test]
+    fn test_classify_url() {
+        let service = service();
+        let response = service.handle("POST",
+                                      "/classify",
+                                      "https://www.reddit.com/r/rust/comments/4u0003/post");
+        assert_eq!(response.status, 200);
+        assert_eq!(json(&response).find("id").and_then(|i| i.as_str()), Some("4u0003"));
+    }
+
+    #[test]
+    fn test_batch_reports_errors_per_item() {
+        let body = r#"[{"url": "https://www.reddit.com/r/rust/comments/4u0003/post"},
+                       {"url": "https://www.reddit.com/r/rust/comments/missing"}]"#;
+        let response = service().handle("POST", "/classify/batch", body);
+        assert_eq!(response.status, 200);
+
+        let items = json(&response);
+        let items = items.as_array().unwrap();
+        assert!(items[0].find("result").is_some());
+        assert!(items[1].find("error").is_some());
+    }
+
+    #[test]
+    fn test_unknown_routes() {
+        let service = service();
+        assert_eq!(service.handle("GET", "/nope", "").status, 404);
+        assert_eq!(service.handle("GET", "/classify", "").status, 405);
+        assert_eq!(service.handle("POST", "/classify", "{\"title\": 1}").status, 400);
+    }
+}
@@ -0,0 +1,469 @@
+use serde_json;
+
+use alert::Alert;
+use error::{Error, Result};
+use model::{Contribution, Deviation};
+use transport::{HyperTransport, Request, Transport};
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+
+/// Somewhere alerts are delivered to
+pub trait AlertSink {
+    fn send(&mut self, alert: &Alert) -> Result<()>;
+}
+
+/// Writes every alert as a line of JSON
+pub struct JsonLinesSink<W: Write> {
+    out: W,
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    pub fn new(out: W) -> JsonLinesSink<W> {
+        JsonLinesSink { out: out }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl JsonLinesSink<io::Stdout> {
+    pub fn stdout() -> JsonLinesSink<io::Stdout> {
+        JsonLinesSink::new(io::stdout())
+    }
+}
+
+impl JsonLinesSink<File> {
+    /// Appends to the file at `path`, creating it if needed
+    pub fn append(path: &str) -> Result<JsonLinesSink<File>> {
+        let file = try!(OpenOptions::new().create(true).append(true).open(path));
+        Ok(JsonLinesSink::new(file))
+    }
+}
+
+impl<W: Write> AlertSink for JsonLinesSink<W> {
+    fn send(&mut self, alert: &Alert) -> Result<()> {
+        try!(writeln!(self.out, "{}", try!(serde_json::to_string(alert))));
+        try!(self.out.flush());
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    id: &'a str,
+    title: &'a str,
+    permalink: String,
+    label: &'a str,
+    probability: f32,
+    /// The features the prediction was based on
+    contributions: &'a [Contribution],
+    /// How far the post is from the training posts, not what the prediction was based on
+    unusual_features: &'a [Deviation],
+}
+
+/// POSTs every alert as JSON to a url
+pub struct WebhookSink<T: Transport = HyperTransport> {
+    url: String,
+    transport: T,
+}
+
+impl WebhookSink<HyperTransport> {
+    pub fn new(url: &str) -> WebhookSink<HyperTransport> {
+        WebhookSink::with_transport(url, HyperTransport::new())
+    }
+}
+
+impl<T: Transport> WebhookSink<T> {
+    pub fn with_transport(url: &str, transport: T) -> WebhookSink<T> {
+        WebhookSink {
+            url: url.to_owned(),
+            transport: transport,
+        }
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+}
+
+impl<T: Transport> AlertSink for WebhookSink<T> {
+    fn send(&mut self, alert: &Alert) -> Result<()> {
+        let payload = WebhookPayload {
+            id: &alert.id,
+            title: &alert.title,
+            permalink: alert.url(),
+            label: &alert.label,
+            probability: alert.probability,
+            contributions: &alert.contributions,
+            unusual_features: &alert.unusual_features,
+        };
+        let request = Request::post(&self.url, try!(serde_json::to_string(&payload)))
+                          .header("Content-Type", "application/json");
+
+        let response = try!(self.transport.send(&request));
+        if !response.is_success() {
+            return Err(Error::Status(response.status));
+        }
+        Ok(())
+    }
+}
+
+/// Mails every alert through an SMTP server that accepts mail without authentication, e.g. a
+/// local relay
+pub struct SmtpSink {
+    addr: String,
+    from: String,
+    to: Vec<String>,
+    hostname: String,
+}
+
+impl SmtpSink {
+    /// `addr` is the `host:port` of the server
+    pub fn new(addr: &str, from: &str, to: Vec<String>) -> SmtpSink {
+        SmtpSink {
+            addr: addr.to_owned(),
+            from: from.to_owned(),
+            to: to,
+            hostname: "localhost".to_owned(),
+        }
+    }
+
+    /// The name to greet the server with. Defaults to localhost
+    pub fn hostname(mut self, hostname: &str) -> SmtpSink {
+        self.hostname = hostname.to_owned();
+        self
+    }
+
+    fn message(&self, alert: &Alert) -> String {
+        let mut body = format!("{}\r\n\r\nPredicted /r/{} with probability {:.3}\r\n",
+                               alert.url(),
+                               alert.label,
+                               alert.probability);
+        if !alert.contributions.is_empty() {
+            body.push_str("\r\nFeatures the prediction was based on:\r\n");
+            for contribution in &alert.contributions {
+                body.push_str(&format!("  {} = {} ({:+.3})\r\n",
+                                       contribution.feature,
+                                       contribution.value,
+                                       contribution.contribution));
+            }
+        }
+        if !alert.unusual_features.is_empty() {
+            body.push_str("\r\nFeatures furthest from the training posts (not necessarily \
+                           what the prediction was based on):\r\n");
+            for deviation in &alert.unusual_features {
+                body.push_str(&format!("  {} = {} (z {:.2})\r\n",
+                                       deviation.feature,
+                                       deviation.value,
+                                       deviation.z_score));
+            }
+        }
+
+        // Lines starting with a dot would otherwise end the DATA section early
+        let body: Vec<String> = body.split("\r\n")
+                                    .map(|line| {
+                                        if line.starts_with('.') {
+                                            format!(".{}", line)
+                                        } else {
+                                            line.to_owned()
+                                        }
+                                    })
+                                    .collect();
+
+        // Header values must stay on one line
+        let subject = alert.title.replace('\r', " ").replace('\n', " ");
+        format!("From: {}\r\nTo: {}\r\nSubject: [{}] {}\r\n\r\n{}\r\n.\r\n",
+                self.from,
+                self.to.join(", "),
+                alert.label,
+                subject,
+                body.join("\r\n"))
+    }
+}
+
+/// Reads a possibly multi line reply and checks its status code
+fn expect_reply<R: BufRead>(reader: &mut R, code: &str) -> Result<()> {
+    loop {
+        let mut line = String::new();
+        if try!(reader.read_line(&mut line)) == 0 {
+            return Err(Error::Smtp("connection closed".to_owned()));
+        }
+        if !line.starts_with(code) {
+            return Err(Error::Smtp(line.trim_right().to_owned()));
+        }
+        // `250-...` continues the reply, `250 ...` ends it
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return Ok(());
+        }
+    }
+}
+
+impl AlertSink for SmtpSink {
+    fn send(&mut self, alert: &Alert) -> Result<()> {
+        let mut stream = try!(TcpStream::connect(self.addr.as_str()));
+        let mut reader = BufReader::new(try!(stream.try_clone()));
+
+        try!(expect_reply(&mut reader, "220"));
+        try!(write!(stream, "HELO {}\r\n", self.hostname));
+        try!(expect_reply(&mut reader, "250"));
+        try!(write!(stream, "MAIL FROM:<{}>\r\n", self.from));
+        try!(expect_reply(&mut reader, "250"));
+        for to in &self.to {
+            try!(write!(stream, "RCPT TO:<{}>\r\n", to));
+            try!(expect_reply(&mut reader, "250"));
+        }
+        try!(write!(stream, "DATA\r\n"));
+        try!(expect_reply(&mut reader, "354"));
+        try!(stream.write_all(self.message(alert).as_bytes()));
+        try!(expect_reply(&mut reader, "250"));
+        try!(write!(stream, "QUIT\r\n"));
+        Ok(())
+    }
+}
+
+/// The settings of one sink, as read from a deployment's JSON configuration, e.g.
+///
+/// ```json
+/// [{"type": "stdout"},
+///  {"type": "file", "path": "alerts.jsonl"},
+///  {"type": "webhook", "url": "https://example.com/hook"},
+///  {"type": "smtp", "addr": "localhost:25", "from": "bot@example.com",
+///   "to": ["mods@example.com"], "hostname": "alerts.example.com"}]
+/// ```
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SinkConfig {
+    /// One of `stdout`, `file`, `webhook` or `smtp`
+    #[serde(rename="type")]
+    pub kind: String,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub addr: Option<String>,
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub to: Vec<String>,
+    /// The name the smtp sink greets the server with. Defaults to localhost
+    #[serde(default)]
+    pub hostname: Option<String>,
+}
+
+/// `value` unless it could break out of the SMTP command or header it is written into
+fn smtp_value<'a>(value: &'a str, name: &str) -> Result<&'a str> {
+    if value.chars().any(|c| "\r\n<>".contains(c)) {
+        return Err(Error::Config(format!("smtp sink `{}` contains a line break or angle \
+                                          bracket: {:?}",
+                                         name,
+                                         value)));
+    }
+    Ok(value)
+}
+
+impl SinkConfig {
+    fn require<'a>(&self, field: &'a Option<String>, name: &str) -> Result<&'a str> {
+        field.as_ref()
+             .map(|f| f.as_str())
+             .ok_or_else(|| Error::Config(format!("{} sink requires `{}`", self.kind, name)))
+    }
+
+    pub fn build(&self) -> Result<Box<AlertSink>> {
+        let sink: Box<AlertSink> = match self.kind.as_str() {
+            "stdout" => Box::new(JsonLinesSink::stdout()),
+            "file" => Box::new(try!(JsonLinesSink::append(try!(self.require(&self.path, "path"))))),
+            "webhook" => Box::new(WebhookSink::new(try!(self.require(&self.url, "url")))),
+            "smtp" => {
+                if self.to.is_empty() {
+                    return Err(Error::Config("smtp sink requires `to`".to_owned()));
+                }
+                let from = try!(smtp_value(try!(self.require(&self.from, "from")), "from"));
+                for to in &self.to {
+                    try!(smtp_value(to, "to"));
+                }
+                let mut sink = SmtpSink::new(try!(self.require(&self.addr, "addr")),
+                                             from,
+                                             self.to.clone());
+                if let Some(ref hostname) = self.hostname {
+                    sink = sink.hostname(try!(smtp_value(hostname, "hostname")));
+                }
+                Box::new(sink)
+            }
+            kind => return Err(Error::Config(format!("unknown sink type `{}`", kind))),
+        };
+        Ok(sink)
+    }
+}
+
+/// Reads a JSON array of `SinkConfig` from `path` and builds every sink
+pub fn load_sinks(path: &str) -> Result<Vec<Box<AlertSink>>> {
+    let configs: Vec<SinkConfig> = try!(serde_json::from_reader(try!(File::open(path))));
+    configs.iter().map(|config| config.build()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::expect_reply;
+    use alert::Alert;
+    use model::{Contribution, Deviation};
+    use transport::{FixtureTransport, Method, Response};
+
+    use serde_json::{self, Value};
+
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn alert() -> Alert {
+        Alert {
+            id: "4u0002".to_owned(),
+            title: "Any good servers?".to_owned(),
+            author: "raider".to_owned(),
+            permalink: "/r/rust/comments/4u0002/post/".to_owned(),
+            created_utc: 1469750500.0,
+            label: "playrust".to_owned(),
+            probability: 0.9,
+            contributions: vec![Contribution {
+                                    feature: "word:server".to_owned(),
+                                    value: 1.0,
+                                    contribution: 0.4,
+                                }],
+            unusual_features: vec![Deviation {
+                               feature: "word:base".to_owned(),
+                               value: 1.0,
+                               z_score: 3.0,
+                           }],
+        }
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let mut sink = JsonLinesSink::new(vec![]);
+        sink.send(&alert()).unwrap();
+        sink.send(&alert()).unwrap();
+
+        let out = String::from_utf8(sink.into_inner()).unwrap();
+        assert_eq!(out.lines().count(), 2);
+        let line: Value = serde_json::from_str(out.lines().next().unwrap()).unwrap();
+        assert_eq!(line.find("id").and_then(|i| i.as_str()), Some("4u0002"));
+    }
+
+    #[test]
+    fn test_webhook() {
+        let url = "http://localhost/hook";
+        let mut transport = FixtureTransport::new();
+        transport.insert(Method::Post, url, Response::ok(String::new()));
+        let mut sink = WebhookSink::with_transport(url, transport);
+        sink.send(&alert()).unwrap();
+
+        let body = sink.transport().requests()[0].body.clone().unwrap();
+        let payload: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(payload.find("permalink").and_then(|p| p.as_str()),
+                   Some("https://www.reddit.com/r/rust/comments/4u0002/post/"));
+        assert_eq!(payload.find("contributions")
+                          .and_then(|f| f.as_array())
+                          .map(|f| f.len()),
+                   Some(1));
+        assert_eq!(payload.find("unusual_features")
+                          .and_then(|f| f.as_array())
+                          .map(|f| f.len()),
+                   Some(1));
+
+        let mut failing = WebhookSink::with_transport("http://localhost/missing",
+                                                      FixtureTransport::new());
+        assert!(failing.send(&alert()).is_err());
+    }
+
+    #[test]
+    fn test_smtp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        // A stand-in server that accepts everything and returns what it was sent
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut received = Vec::new();
+            write!(stream, "220 localhost ready\r\n").unwrap();
+
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_right().to_owned();
+                received.push(line.clone());
+
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        write!(stream, "250 queued\r\n").unwrap();
+                    }
+                } else if line == "DATA" {
+                    in_data = true;
+                    write!(stream, "354 go ahead\r\n").unwrap();
+                } else if line == "QUIT" {
+                    break;
+                } else if line.starts_with("HELO") {
+                    write!(stream, "250-localhost\r\n250 ok\r\n").unwrap();
+                } else {
+                    write!(stream, "250 ok\r\n").unwrap();
+                }
+            }
+            received
+        });
+
+        let mut sink = SmtpSink::new(&addr, "bot@example.com", vec!["mods@example.com".to_owned()]);
+        sink.send(&alert()).unwrap();
+
+        let received = server.join().unwrap();
+        assert!(received.contains(&"RCPT TO:<mods@example.com>".to_owned()));
+        assert!(received.contains(&"Subject: [playrust] Any good servers?".to_owned()));
+        assert!(received.contains(&"  word:server = 1 (+0.400)".to_owned()));
+        assert_eq!(received.last().map(|l| l.as_str()), Some("QUIT"));
+    }
+
+    #[test]
+    fn test_smtp_rejection() {
+        let mut reply = BufReader::new(&b"550 no such user\r\n"[..]);
+        match expect_reply(&mut reply, "250") {
+            Err(::error::Error::Smtp(ref message)) => assert_eq!(message, "550 no such user"),
+            other => panic!("Expected an smtp error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_config() {
+        let configs: Vec<SinkConfig> =
+            serde_json::from_str(r#"[{"type": "stdout"}, {"type": "webhook"}]"#).unwrap();
+        assert_eq!(configs[0].kind, "stdout");
+        assert!(configs[0].build().is_ok());
+        assert!(configs[1].build().is_err());
+    }
+
+    #[test]
+    fn test_smtp_config_rejects_injection() {
+        let smtp = |from: &str, to: &str, hostname: &str| {
+            SinkConfig {
+                kind: "smtp".to_owned(),
+                path: None,
+                url: None,
+                addr: Some("localhost:25".to_owned()),
+                from: Some(from.to_owned()),
+                to: vec![to.to_owned()],
+                hostname: Some(hostname.to_owned()),
+            }
+        };
+        assert!(smtp("bot@example.com", "mods@example.com", "example.com").build().is_ok());
+        assert!(smtp("bot@example.com>\r\nRCPT TO:<x@evil.com", "mods@example.com", "example.com")
+                    .build()
+                    .is_err());
+        assert!(smtp("bot@example.com", "mods@example.com>", "example.com").build().is_err());
+        assert!(smtp("bot@example.com", "mods@example.com", "example.com\nQUIT").build().is_err());
+    }
+}
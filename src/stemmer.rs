@@ -0,0 +1,329 @@
+//! The Porter stemming algorithm for English, after Martin Porter's reference implementation.
+//!
+//! See M.F. Porter, "An algorithm for suffix stripping", Program 14(3), 1980.
+
+struct Stemmer {
+    b: Vec<u8>,
+    /// Index of the last letter of the stem being built
+    k: isize,
+    /// Index of the last letter before the suffix matched by `ends`
+    j: isize,
+}
+
+impl Stemmer {
+    fn at(&self, i: isize) -> u8 {
+        self.b[i as usize]
+    }
+
+    /// Whether the letter at `i` is a consonant. `y` is one unless it follows a consonant.
+    fn cons(&self, i: isize) -> bool {
+        match self.at(i) {
+            b'a' | b'e' | b'i' | b'o' | b'u' => false,
+            b'y' => i == 0 || !self.cons(i - 1),
+            _ => true,
+        }
+    }
+
+    /// The number of vowel-consonant sequences in `b[0..=j]`
+    fn m(&self) -> usize {
+        let mut n = 0;
+        let mut i = 0;
+        loop {
+            if i > self.j {
+                return n;
+            }
+            if !self.cons(i) {
+                break;
+            }
+            i += 1;
+        }
+        i += 1;
+        loop {
+            loop {
+                if i > self.j {
+                    return n;
+                }
+                if self.cons(i) {
+                    break;
+                }
+                i += 1;
+            }
+            i += 1;
+            n += 1;
+            loop {
+                if i > self.j {
+                    return n;
+                }
+                if !self.cons(i) {
+                    break;
+                }
+                i += 1;
+            }
+            i += 1;
+        }
+    }
+
+    fn vowel_in_stem(&self) -> bool {
+        (0..self.j + 1).any(|i| !self.cons(i))
+    }
+
+    fn double_cons(&self, i: isize) -> bool {
+        i >= 1 && self.at(i) == self.at(i - 1) && self.cons(i)
+    }
+
+    /// Whether `b[i - 2..=i]` is consonant-vowel-consonant and the last consonant is not `w`,
+    /// `x` or `y`, like `hop` but not `snow`
+    fn cvc(&self, i: isize) -> bool {
+        if i < 2 || !self.cons(i) || self.cons(i - 1) || !self.cons(i - 2) {
+            return false;
+        }
+        match self.at(i) {
+            b'w' | b'x' | b'y' => false,
+            _ => true,
+        }
+    }
+
+    /// Whether `b[0..=k]` ends with `s`, setting `j` to the end of the rest if so
+    fn ends(&mut self, s: &str) -> bool {
+        let s = s.as_bytes();
+        let len = s.len() as isize;
+        if len > self.k + 1 {
+            return false;
+        }
+        let start = (self.k + 1 - len) as usize;
+        if &self.b[start..(self.k + 1) as usize] != s {
+            return false;
+        }
+        self.j = self.k - len;
+        true
+    }
+
+    /// Replaces `b[j + 1..=k]` with `s`
+    fn set_to(&mut self, s: &str) {
+        let start = (self.j + 1) as usize;
+        self.b.truncate(start);
+        self.b.extend_from_slice(s.as_bytes());
+        self.k = self.j + s.len() as isize;
+    }
+
+    fn replace_if_measured(&mut self, s: &str) {
+        if self.m() > 0 {
+            self.set_to(s);
+        }
+    }
+
+    /// Removes the suffix `b[j + 1..=k]`
+    fn truncate_to_j(&mut self) {
+        self.k = self.j;
+        self.b.truncate((self.k + 1) as usize);
+    }
+
+    /// Plurals and `-ed` or `-ing`, e.g. caresses -> caress, ponies -> poni, motoring -> motor
+    fn step1ab(&mut self) {
+        if self.at(self.k) == b's' {
+            if self.ends("sses") {
+                self.k -= 2;
+            } else if self.ends("ies") {
+                self.set_to("i");
+            } else if self.at(self.k - 1) != b's' {
+                self.k -= 1;
+            }
+            self.b.truncate((self.k + 1) as usize);
+        }
+
+        if self.ends("eed") {
+            if self.m() > 0 {
+                self.k -= 1;
+                self.b.truncate((self.k + 1) as usize);
+            }
+        } else if (self.ends("ed") || self.ends("ing")) && self.vowel_in_stem() {
+            self.truncate_to_j();
+            if self.ends("at") {
+                self.set_to("ate");
+            } else if self.ends("bl") {
+                self.set_to("ble");
+            } else if self.ends("iz") {
+                self.set_to("ize");
+            } else if self.double_cons(self.k) {
+                match self.at(self.k) {
+                    b'l' | b's' | b'z' => (),
+                    _ => {
+                        self.k -= 1;
+                        self.b.truncate((self.k + 1) as usize);
+                    }
+                }
+            } else {
+                self.j = self.k;
+                if self.m() == 1 && self.cvc(self.k) {
+                    self.set_to("e");
+                }
+            }
+        }
+    }
+
+    /// A terminal `y` after a vowel in the stem becomes `i`
+    fn step1c(&mut self) {
+        if self.ends("y") && self.vowel_in_stem() {
+            let k = self.k as usize;
+            self.b[k] = b'i';
+        }
+    }
+
+    /// Double suffixes to single ones, e.g. -ization -> -ize
+    fn step2(&mut self) {
+        let rules: &[(&str, &str)] = match self.at(self.k - 1) {
+            b'a' => &[("ational", "ate"), ("tional", "tion")],
+            b'c' => &[("enci", "ence"), ("anci", "ance")],
+            b'e' => &[("izer", "ize")],
+            b'l' => {
+                &[("bli", "ble"), ("alli", "al"), ("entli", "ent"), ("eli", "e"), ("ousli", "ous")]
+            }
+            b'o' => &[("ization", "ize"), ("ation", "ate"), ("ator", "ate")],
+            b's' => &[("alism", "al"), ("iveness", "ive"), ("fulness", "ful"), ("ousness", "ous")],
+            b't' => &[("aliti", "al"), ("iviti", "ive"), ("biliti", "ble")],
+            b'g' => &[("logi", "log")],
+            _ => &[],
+        };
+        self.apply(rules);
+    }
+
+    /// -ic-, -full, -ness etc.
+    fn step3(&mut self) {
+        let rules: &[(&str, &str)] = match self.at(self.k) {
+            b'e' => &[("icate", "ic"), ("ative", ""), ("alize", "al")],
+            b'i' => &[("iciti", "ic")],
+            b'l' => &[("ical", "ic"), ("ful", "")],
+            b's' => &[("ness", "")],
+            _ => &[],
+        };
+        self.apply(rules);
+    }
+
+    /// Replaces the first suffix of `rules` that ends the word, if the rest has a measure
+    fn apply(&mut self, rules: &[(&str, &str)]) {
+        for &(suffix, replacement) in rules {
+            if self.ends(suffix) {
+                self.replace_if_measured(replacement);
+                return;
+            }
+        }
+    }
+
+    /// Removes -ant, -ence etc. from stems with a measure above one
+    fn step4(&mut self) {
+        let suffixes: &[&str] = match self.at(self.k - 1) {
+            b'a' => &["al"],
+            b'c' => &["ance", "ence"],
+            b'e' => &["er"],
+            b'i' => &["ic"],
+            b'l' => &["able", "ible"],
+            b'n' => &["ant", "ement", "ment", "ent"],
+            b'o' => &["ion", "ou"],
+            b's' => &["ism"],
+            b't' => &["ate", "iti"],
+            b'u' => &["ous"],
+            b'v' => &["ive"],
+            b'z' => &["ize"],
+            _ => &[],
+        };
+
+        let mut found = false;
+        for suffix in suffixes {
+            if self.ends(suffix) {
+                // -ion is only removed after s or t
+                found = *suffix != "ion" ||
+                        self.j >= 0 && (self.at(self.j) == b's' || self.at(self.j) == b't');
+                break;
+            }
+        }
+        if found && self.m() > 1 {
+            self.truncate_to_j();
+        }
+    }
+
+    /// Removes a final -e and turns -ll into -l in longer stems
+    fn step5(&mut self) {
+        self.j = self.k;
+        if self.at(self.k) == b'e' {
+            let m = self.m();
+            if m > 1 || m == 1 && !self.cvc(self.k - 1) {
+                self.k -= 1;
+                self.b.truncate((self.k + 1) as usize);
+            }
+        }
+        if self.at(self.k) == b'l' && self.double_cons(self.k) && self.m() > 1 {
+            self.k -= 1;
+            self.b.truncate((self.k + 1) as usize);
+        }
+    }
+}
+
+/// Stems a lowercase English word, e.g. `raided` and `raiding` both become `raid`. Words of
+/// less than three letters and words with anything but ASCII lowercase letters are returned
+/// unchanged.
+pub fn stem(word: &str) -> String {
+    if word.len() < 3 || !word.bytes().all(|b| b'a' <= b && b <= b'z') {
+        return word.to_owned();
+    }
+
+    let mut stemmer = Stemmer {
+        b: word.as_bytes().to_vec(),
+        k: word.len() as isize - 1,
+        j: 0,
+    };
+    stemmer.step1ab();
+    if stemmer.k > 0 {
+        stemmer.step1c();
+        stemmer.step2();
+        stemmer.step3();
+        stemmer.step4();
+        stemmer.step5();
+    }
+
+    stemmer.b.truncate((stemmer.k + 1) as usize);
+    String::from_utf8(stemmer.b).unwrap_or(word.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_porter_examples() {
+        let examples = [("caresses", "caress"),
+                        ("ponies", "poni"),
+                        ("cats", "cat"),
+                        ("feed", "feed"),
+                        ("agreed", "agre"),
+                        ("plastered", "plaster"),
+                        ("motoring", "motor"),
+                        ("sing", "sing"),
+                        ("conflated", "conflat"),
+                        ("troubled", "troubl"),
+                        ("sized", "size"),
+                        ("hopping", "hop"),
+                        ("falling", "fall"),
+                        ("filing", "file"),
+                        ("happy", "happi"),
+                        ("relational", "relat"),
+                        ("generalization", "gener"),
+                        ("hopeful", "hope"),
+                        ("goodness", "good"),
+                        ("adjustment", "adjust"),
+                        ("controll", "control")];
+        for &(word, expected) in &examples {
+            assert_eq!(stem(word), expected, "stem of {}", word);
+        }
+    }
+
+    #[test]
+    fn test_domain_words() {
+        assert_eq!(stem("raided"), "raid");
+        assert_eq!(stem("raiding"), "raid");
+        assert_eq!(stem("cargos"), "cargo");
+        assert_eq!(stem("class"), "class");
+        assert_eq!(stem("crates"), stem("crate"));
+        assert_eq!(stem("c4"), "c4");
+        assert_eq!(stem("größe"), "größe");
+    }
+}
@@ -0,0 +1,238 @@
+use serde_json;
+
+use error::Result;
+use reddit::RawPostFeatures;
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+const POSTS_FILE: &'static str = "posts.jsonl";
+const CHECKPOINT_FILE: &'static str = "checkpoint.json";
+
+/// Progress of a scrape, saved after every page so it can resume after being interrupted
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Checkpoint {
+    /// The `after` cursor of the next page to backfill
+    pub after: Option<String>,
+    /// Set once the backfill reached the end of reddit's listing
+    pub backfill_complete: bool,
+}
+
+/// Scraped posts keyed by post id, persisted in a directory.
+///
+/// Posts are appended to a JSON Lines file as they are inserted, so an interruption loses at
+/// most the line being written, which is skipped when the store is reopened. A complete line
+/// that lost only its newline is kept, and the newline restored.
+pub struct PostStore {
+    dir: PathBuf,
+    posts: BTreeMap<String, RawPostFeatures>,
+    log: File,
+    checkpoint: Checkpoint,
+}
+
+impl PostStore {
+    /// Opens the store in `dir`, creating it if it does not exist yet
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<PostStore> {
+        let dir = dir.as_ref().to_owned();
+        try!(fs::create_dir_all(&dir));
+
+        let posts_path = dir.join(POSTS_FILE);
+        let mut posts = BTreeMap::new();
+
+        // Whether the last line is a complete post missing its newline, which the next append
+        // must not be joined onto
+        let mut unterminated = false;
+
+        if posts_path.is_file() {
+            let mut bytes = Vec::new();
+            try!(try!(File::open(&posts_path)).read_to_end(&mut bytes));
+            // An interrupted write may also cut a character short, which only the last line can
+            // contain and which then fails to parse
+            let contents = String::from_utf8_lossy(&bytes);
+
+            let lines: Vec<&str> = contents.lines().collect();
+            let last = lines.len().saturating_sub(1);
+            let mut valid_len = 0;
+            for (index, line) in lines.iter().enumerate() {
+                match serde_json::from_str::<RawPostFeatures>(line) {
+                    Ok(post) => {
+                        valid_len += line.len() as u64 + 1;
+                        posts.insert(post.id.clone(), post);
+                        unterminated = index == last && !contents.ends_with('\n');
+                    }
+                    // A line cut short by an interrupted write, drop it so appends start on a
+                    // fresh line
+                    Err(_) if index == last => {
+                        try!(try!(OpenOptions::new().write(true).open(&posts_path))
+                                 .set_len(valid_len));
+                    }
+                    Err(e) => return Err(From::from(e)),
+                }
+            }
+        }
+
+        let checkpoint_path = dir.join(CHECKPOINT_FILE);
+        let checkpoint = if checkpoint_path.is_file() {
+            let mut contents = String::new();
+            try!(try!(File::open(&checkpoint_path)).read_to_string(&mut contents));
+            try!(serde_json::from_str(&contents))
+        } else {
+            Checkpoint::default()
+        };
+
+        let mut log = try!(OpenOptions::new().create(true).append(true).open(&posts_path));
+        if unterminated {
+            try!(log.write_all(b"\n"));
+            try!(log.flush());
+        }
+
+        Ok(PostStore {
+            dir: dir,
+            posts: posts,
+            log: log,
+            checkpoint: checkpoint,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.posts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.posts.is_empty()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.posts.contains_key(id)
+    }
+
+    pub fn posts(&self) -> Vec<&RawPostFeatures> {
+        self.posts.values().collect()
+    }
+
+    /// The most recently created post in the store
+    pub fn newest(&self) -> Option<&RawPostFeatures> {
+        self.posts
+            .values()
+            .fold(None, |newest: Option<&RawPostFeatures>, post| {
+                match newest {
+                    Some(n) if n.created_utc >= post.created_utc => Some(n),
+                    _ => Some(post),
+                }
+            })
+    }
+
+    /// Persists `post` unless a post with the same id is already stored. Returns whether it was
+    /// inserted.
+    pub fn insert(&mut self, post: RawPostFeatures) -> Result<bool> {
+        if self.posts.contains_key(&post.id) {
+            return Ok(false);
+        }
+
+        let line = try!(serde_json::to_string(&post));
+        try!(writeln!(self.log, "{}", line));
+        try!(self.log.flush());
+
+        self.posts.insert(post.id.clone(), post);
+        Ok(true)
+    }
+
+    pub fn checkpoint(&self) -> &Checkpoint {
+        &self.checkpoint
+    }
+
+    /// Replaces the checkpoint, writing it to a temporary file first so an interruption never
+    /// leaves a partial checkpoint behind
+    pub fn set_checkpoint(&mut self, checkpoint: Checkpoint) -> Result<()> {
+        let tmp_path = self.dir.join(format!("{}.tmp", CHECKPOINT_FILE));
+        {
+            let mut f = try!(File::create(&tmp_path));
+            try!(f.write_all(try!(serde_json::to_string(&checkpoint)).as_bytes()));
+            try!(f.sync_all());
+        }
+        try!(fs::rename(&tmp_path, self.dir.join(CHECKPOINT_FILE)));
+
+        self.checkpoint = checkpoint;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::POSTS_FILE;
+    use reddit::RawPostFeatures;
+    use util::temp_dir;
+
+    use serde_json;
+
+    use std::fs::{self, File, OpenOptions};
+    use std::io::prelude::*;
+
+    fn post(id: &str, created_utc: f64) -> RawPostFeatures {
+        RawPostFeatures {
+            id: id.to_owned(),
+            created_utc: created_utc,
+            ..RawPostFeatures::default()
+        }
+    }
+
+    #[test]
+    fn test_reopen() {
+        let dir = temp_dir("store_reopen");
+        {
+            let mut store = PostStore::open(&dir).unwrap();
+            assert!(store.insert(post("a", 2.0)).unwrap());
+            assert!(store.insert(post("b", 1.0)).unwrap());
+            assert!(!store.insert(post("a", 2.0)).unwrap());
+            store.set_checkpoint(Checkpoint {
+                     after: Some("t3_b".to_owned()),
+                     backfill_complete: false,
+                 })
+                 .unwrap();
+        }
+
+        let store = PostStore::open(&dir).unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.newest().map(|p| p.id.as_str()), Some("a"));
+        assert_eq!(store.checkpoint().after, Some("t3_b".to_owned()));
+    }
+
+    #[test]
+    fn test_truncated_line_is_skipped() {
+        let dir = temp_dir("store_truncated");
+        {
+            let mut store = PostStore::open(&dir).unwrap();
+            store.insert(post("a", 1.0)).unwrap();
+        }
+        {
+            let mut log = OpenOptions::new().append(true).open(dir.join(POSTS_FILE)).unwrap();
+            write!(log, "{{\"is_self\": tr").unwrap();
+        }
+
+        let mut store = PostStore::open(&dir).unwrap();
+        assert_eq!(store.len(), 1);
+
+        store.insert(post("b", 2.0)).unwrap();
+        assert_eq!(PostStore::open(&dir).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_missing_final_newline_is_restored() {
+        let dir = temp_dir("store_unterminated");
+        fs::create_dir_all(&dir).unwrap();
+        let line = serde_json::to_string(&post("a", 1.0)).unwrap();
+        File::create(dir.join(POSTS_FILE)).unwrap().write_all(line.as_bytes()).unwrap();
+
+        let mut store = PostStore::open(&dir).unwrap();
+        assert_eq!(store.len(), 1);
+        store.insert(post("b", 2.0)).unwrap();
+
+        let store = PostStore::open(&dir).unwrap();
+        assert_eq!(store.len(), 2);
+        assert!(store.contains("a") && store.contains("b"));
+    }
+}
@@ -0,0 +1,212 @@
+use stemmer::stem;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Common English words dropped by `Tokenizer`, sorted. Words that are also Rust keywords, like
+/// `as`, `for`, `if`, `in`, `self`, `where` and `while`, are kept since they hint at code.
+const STOP_WORDS: [&'static str; 97] = ["a", "about", "after", "again", "all", "am", "an", "and",
+                                        "any", "are", "at", "be", "because", "been", "before",
+                                        "being", "between", "both", "but", "by", "can", "could",
+                                        "did", "does", "doing", "during", "each", "few", "from",
+                                        "further", "had", "has", "have", "having", "he", "her",
+                                        "here", "hers", "him", "his", "how", "i", "into", "is",
+                                        "it", "its", "just", "me", "more", "most", "my", "no",
+                                        "nor", "not", "now", "of", "off", "on", "once", "only",
+                                        "or", "other", "our", "out", "over", "own", "same", "she",
+                                        "should", "so", "some", "such", "than", "that", "the",
+                                        "their", "them", "then", "there", "these", "they",
+                                        "this", "those", "through", "to", "too", "under", "until",
+                                        "up", "very", "was", "we", "were", "what", "when", "which",
+                                        "who"];
+
+/// Splits text into normalized terms for the word frequency features.
+///
+/// Text is segmented into words by the Unicode word boundary rules, identifiers like
+/// `snake_case` and `CamelCase` are split into their parts, and every part is lowercased,
+/// filtered by length, checked against a list of English stop words and reduced to its Porter
+/// stem. Every step but segmentation and the length filter can be turned off.
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct Tokenizer {
+    /// Tokens of fewer characters are dropped
+    min_len: usize,
+    /// Tokens of more characters are dropped
+    max_len: usize,
+    lowercase: bool,
+    stem: bool,
+    remove_stop_words: bool,
+    split_identifiers: bool,
+}
+
+impl Default for Tokenizer {
+    fn default() -> Tokenizer {
+        Tokenizer {
+            min_len: 2,
+            max_len: 20,
+            lowercase: true,
+            stem: true,
+            remove_stop_words: true,
+            split_identifiers: true,
+        }
+    }
+}
+
+/// Splits `word` at underscores and at the case changes of `CamelCase`. A run of capitals is
+/// kept together as an acronym, so `HTTPServer` becomes `HTTP` and `Server`.
+fn split_identifier(word: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+
+    for part in word.split('_').filter(|p| !p.is_empty()) {
+        let chars: Vec<(usize, char)> = part.char_indices().collect();
+        let mut start = 0;
+
+        for (n, &(index, c)) in chars.iter().enumerate().skip(1) {
+            let prev = chars[n - 1].1;
+            let next_is_lower = chars.get(n + 1).map_or(false, |&(_, next)| next.is_lowercase());
+            let boundary = c.is_uppercase() &&
+                           (prev.is_lowercase() || prev.is_numeric() ||
+                            prev.is_uppercase() && next_is_lower);
+            if boundary {
+                parts.push(&part[start..index]);
+                start = index;
+            }
+        }
+        parts.push(&part[start..]);
+    }
+
+    parts
+}
+
+impl Tokenizer {
+    pub fn min_len(mut self, min_len: usize) -> Tokenizer {
+        self.min_len = min_len;
+        self
+    }
+
+    pub fn max_len(mut self, max_len: usize) -> Tokenizer {
+        self.max_len = max_len;
+        self
+    }
+
+    pub fn lowercase(mut self, lowercase: bool) -> Tokenizer {
+        self.lowercase = lowercase;
+        self
+    }
+
+    pub fn stem(mut self, stem: bool) -> Tokenizer {
+        self.stem = stem;
+        self
+    }
+
+    pub fn remove_stop_words(mut self, remove_stop_words: bool) -> Tokenizer {
+        self.remove_stop_words = remove_stop_words;
+        self
+    }
+
+    pub fn split_identifiers(mut self, split_identifiers: bool) -> Tokenizer {
+        self.split_identifiers = split_identifiers;
+        self
+    }
+
+    /// Splits `text` into its normalized terms, in order of appearance
+    pub fn tokenize(&self, text: &str) -> Vec<String> {
+        let mut tokens = Vec::new();
+
+        for word in text.unicode_words() {
+            let parts = if self.split_identifiers {
+                split_identifier(word)
+            } else {
+                vec![word]
+            };
+
+            for part in parts {
+                let token = if self.lowercase {
+                    part.to_lowercase()
+                } else {
+                    part.to_owned()
+                };
+
+                let len = token.chars().count();
+                if len < self.min_len || len > self.max_len {
+                    continue;
+                }
+                if self.remove_stop_words && STOP_WORDS.binary_search(&token.as_str()).is_ok() {
+                    continue;
+                }
+
+                tokens.push(if self.stem { stem(&token) } else { token });
+            }
+        }
+
+        tokens
+    }
+
+    /// Normalizes a list of words into a vocabulary of distinct terms, keeping the order in
+    /// which they first appear. Words that are dropped entirely, like stop words, are left out.
+    pub fn vocabulary(&self, words: &[String]) -> Vec<String> {
+        let mut vocabulary: Vec<String> = Vec::with_capacity(words.len());
+        for term in words.iter().flat_map(|w| self.tokenize(w)) {
+            if !vocabulary.contains(&term) {
+                vocabulary.push(term);
+            }
+        }
+        vocabulary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::{split_identifier, STOP_WORDS};
+
+    #[test]
+    fn test_stop_words_are_sorted() {
+        assert!(STOP_WORDS.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_split_identifier() {
+        assert_eq!(split_identifier("snake_case_name"), vec!["snake", "case", "name"]);
+        assert_eq!(split_identifier("CamelCase"), vec!["Camel", "Case"]);
+        assert_eq!(split_identifier("HTTPServer"), vec!["HTTP", "Server"]);
+        assert_eq!(split_identifier("utf8Decoder"), vec!["utf8", "Decoder"]);
+        assert_eq!(split_identifier("__private"), vec!["private"]);
+    }
+
+    #[test]
+    fn test_default_tokenizer() {
+        let tokenizer = Tokenizer::default();
+        assert_eq!(tokenizer.tokenize("The class was raided, and HashMap::new() panicked"),
+                   vec!["class", "raid", "hash", "map", "new", "panick"]);
+        assert_eq!(tokenizer.tokenize("if let Some(x) = parse_json(input)"),
+                   vec!["if", "let", "pars", "json", "input"]);
+    }
+
+    #[test]
+    fn test_unicode_words() {
+        let tokenizer = Tokenizer::default();
+        assert_eq!(tokenizer.tokenize("Größe über café"), vec!["größe", "über", "café"]);
+        assert_eq!(tokenizer.tokenize("Ελληνικά ΚΕΙΜΕΝΟ"), vec!["ελληνικά", "κειμενο"]);
+    }
+
+    #[test]
+    fn test_options() {
+        let raw = Tokenizer::default()
+                      .lowercase(false)
+                      .stem(false)
+                      .remove_stop_words(false)
+                      .split_identifiers(false);
+        assert_eq!(raw.tokenize("The HashMap is_empty"),
+                   vec!["The", "HashMap", "is_empty"]);
+
+        let short = Tokenizer::default().min_len(4).max_len(6).stem(false);
+        assert_eq!(short.tokenize("rust cargo borrowck fn"), vec!["rust", "cargo"]);
+    }
+
+    #[test]
+    fn test_vocabulary() {
+        let words: Vec<String> = vec!["Crates", "crate", "the", "Raiding", "raids"]
+                                     .into_iter()
+                                     .map(String::from)
+                                     .collect();
+        assert_eq!(Tokenizer::default().vocabulary(&words), vec!["crate", "raid"]);
+    }
+}
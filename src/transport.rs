@@ -0,0 +1,322 @@
+use hyper::Client;
+use hyper::header::Headers;
+
+use error::Result;
+
+use std::ascii::AsciiExt;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Method {
+    Get,
+    Post,
+}
+
+impl Method {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            Method::Get => "get",
+            Method::Post => "post",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+}
+
+impl Request {
+    pub fn get(url: &str) -> Request {
+        Request {
+            method: Method::Get,
+            url: url.to_owned(),
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    pub fn post(url: &str, body: String) -> Request {
+        Request {
+            method: Method::Post,
+            url: url.to_owned(),
+            headers: Vec::new(),
+            body: Some(body),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Request {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Response {
+    pub fn ok(body: String) -> Response {
+        Response {
+            status: 200,
+            headers: Vec::new(),
+            body: body,
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        200 <= self.status && self.status < 300
+    }
+
+    /// Returns the first value of the header `name`, compared case insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|&&(ref n, _)| n.eq_ignore_ascii_case(name))
+            .map(|&(_, ref v)| v.as_str())
+    }
+}
+
+/// Sends requests on behalf of `RedditClient`
+pub trait Transport {
+    fn send(&mut self, request: &Request) -> Result<Response>;
+}
+
+pub struct HyperTransport {
+    client: Client,
+}
+
+impl HyperTransport {
+    pub fn new() -> HyperTransport {
+        HyperTransport { client: Client::new() }
+    }
+}
+
+impl Transport for HyperTransport {
+    fn send(&mut self, request: &Request) -> Result<Response> {
+        let mut headers = Headers::new();
+        for &(ref name, ref value) in &request.headers {
+            headers.set_raw(name.clone(), vec![value.clone().into_bytes()]);
+        }
+
+        let builder = match request.method {
+            Method::Get => self.client.get(&request.url),
+            Method::Post => self.client.post(&request.url),
+        };
+        let builder = builder.headers(headers);
+        let builder = match request.body {
+            Some(ref body) => builder.body(body.as_str()),
+            None => builder,
+        };
+
+        let mut res = try!(builder.send());
+
+        let mut body = String::new();
+        try!(res.read_to_string(&mut body));
+
+        Ok(Response {
+            status: res.status.to_u16(),
+            headers: res.headers
+                        .iter()
+                        .map(|h| (h.name().to_owned(), h.value_string()))
+                        .collect(),
+            body: body,
+        })
+    }
+}
+
+/// Percent encodes `params` as an `application/x-www-form-urlencoded` body
+pub fn form_encode(params: &[(&str, &str)]) -> String {
+    fn encode(s: &str) -> String {
+        s.bytes()
+         .map(|b| match b {
+             b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'_' | b'.' | b'~' => {
+                 (b as char).to_string()
+             }
+             b' ' => "+".to_owned(),
+             _ => format!("%{:02X}", b),
+         })
+         .collect()
+    }
+
+    params.iter()
+          .map(|&(name, value)| format!("{}={}", encode(name), encode(value)))
+          .collect::<Vec<_>>()
+          .join("&")
+}
+
+/// Strips the scheme and host from `url`, leaving the path and query
+pub fn path_and_query(url: &str) -> &str {
+    match url.find("://") {
+        Some(scheme_end) => {
+            let rest = &url[scheme_end + 3..];
+            rest.find('/').map(|i| &rest[i..]).unwrap_or("/")
+        }
+        None => url,
+    }
+}
+
+/// The file name a response to `method` on `url` is recorded under.
+///
+/// Only the path and query are used, so fixtures can be served regardless of the base url:
+/// `GET https://www.reddit.com/r/rust/new.json?limit=2` is recorded as
+/// `get_r_rust_new_json_limit_2.json`.
+pub fn fixture_name(method: Method, url: &str) -> String {
+    let path: String = path_and_query(url)
+                           .chars()
+                           .map(|c| if c.is_ascii() && c.is_alphanumeric() { c } else { '_' })
+                           .collect();
+    format!("{}_{}.json", method.as_str(), path.trim_matches('_'))
+}
+
+/// Serves canned responses instead of going to the network.
+///
+/// Responses are either registered in memory or read from a directory of files named by
+/// `fixture_name`. Requests without a fixture receive a 404. Every request sent is kept so tests
+/// can assert on what the client asked for.
+pub struct FixtureTransport {
+    dir: Option<PathBuf>,
+    responses: BTreeMap<(Method, String), Vec<Response>>,
+    requests: Vec<Request>,
+}
+
+impl FixtureTransport {
+    pub fn new() -> FixtureTransport {
+        FixtureTransport {
+            dir: None,
+            responses: BTreeMap::new(),
+            requests: Vec::new(),
+        }
+    }
+
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> FixtureTransport {
+        FixtureTransport { dir: Some(dir.as_ref().to_owned()), ..FixtureTransport::new() }
+    }
+
+    /// Queues `response` for the next request to `url`. Responses queued for the same request
+    /// are served in order, the last one repeating once the others are used up.
+    pub fn insert(&mut self, method: Method, url: &str, response: Response) {
+        self.responses
+            .entry((method, path_and_query(url).to_owned()))
+            .or_insert_with(Vec::new)
+            .push(response);
+    }
+
+    pub fn requests(&self) -> &[Request] {
+        &self.requests
+    }
+
+    fn from_disk(&self, request: &Request) -> Result<Option<Response>> {
+        let path = match self.dir {
+            Some(ref dir) => dir.join(fixture_name(request.method, &request.url)),
+            None => return Ok(None),
+        };
+
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        let mut body = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut body));
+        Ok(Some(Response::ok(body)))
+    }
+}
+
+impl Transport for FixtureTransport {
+    fn send(&mut self, request: &Request) -> Result<Response> {
+        self.requests.push(request.clone());
+
+        let key = (request.method, path_and_query(&request.url).to_owned());
+        if let Some(queued) = self.responses.get_mut(&key) {
+            if queued.len() > 1 {
+                return Ok(queued.remove(0));
+            } else if let Some(response) = queued.first() {
+                return Ok(response.clone());
+            }
+        }
+
+        match try!(self.from_disk(request)) {
+            Some(response) => Ok(response),
+            None => {
+                Ok(Response {
+                    status: 404,
+                    headers: Vec::new(),
+                    body: String::new(),
+                })
+            }
+        }
+    }
+}
+
+/// Forwards requests to another transport and records every successful response into `dir`,
+/// producing fixtures that `FixtureTransport::from_dir` can replay.
+pub struct RecordingTransport<T: Transport> {
+    inner: T,
+    dir: PathBuf,
+}
+
+impl<T: Transport> RecordingTransport<T> {
+    pub fn new<P: AsRef<Path>>(inner: T, dir: P) -> Result<RecordingTransport<T>> {
+        try!(fs::create_dir_all(dir.as_ref()));
+        Ok(RecordingTransport {
+            inner: inner,
+            dir: dir.as_ref().to_owned(),
+        })
+    }
+}
+
+impl<T: Transport> Transport for RecordingTransport<T> {
+    fn send(&mut self, request: &Request) -> Result<Response> {
+        let response = try!(self.inner.send(request));
+
+        if response.is_success() {
+            let path = self.dir.join(fixture_name(request.method, &request.url));
+            let mut f = try!(File::create(path));
+            try!(f.write_all(response.body.as_bytes()));
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixture_name() {
+        assert_eq!(fixture_name(Method::Get,
+                                "https://www.reddit.com/r/rust/new.json?sort=new&limit=2"),
+                   "get_r_rust_new_json_sort_new_limit_2.json");
+        assert_eq!(fixture_name(Method::Post, "http://127.0.0.1:8080/api/v1/access_token"),
+                   "post_api_v1_access_token.json");
+    }
+
+    #[test]
+    fn test_form_encode() {
+        assert_eq!(form_encode(&[("text", "a b&c=d/é"), ("id", "t3_x")]),
+                   "text=a+b%26c%3Dd%2F%C3%A9&id=t3_x");
+    }
+
+    #[test]
+    fn test_queued_responses() {
+        let mut transport = FixtureTransport::new();
+        transport.insert(Method::Get, "http://a/x", Response::ok("1".to_owned()));
+        transport.insert(Method::Get, "http://a/x", Response::ok("2".to_owned()));
+
+        let request = Request::get("http://b/x");
+        assert_eq!(transport.send(&request).unwrap().body, "1");
+        assert_eq!(transport.send(&request).unwrap().body, "2");
+        assert_eq!(transport.send(&request).unwrap().body, "2");
+        assert_eq!(transport.send(&Request::get("http://a/y")).unwrap().status, 404);
+        assert_eq!(transport.requests().len(), 4);
+    }
+}
@@ -7,63 +7,163 @@ use bincode::rustc_serialize::{encode, decode};
 
 use std::fs::File;
 use std::io::prelude::*;
+use std::result;
+use std::str::FromStr;
 
-use csv::Writer;
+use csv::{Decoded, Reader, Writer};
+
+use error::{Error, Result};
+use reddit::{LEGACY_COLUMNS, LegacyRawPostFeatures, RawPostFeatures};
+
+/// Parses the value of the command line option `name`, so a typo is reported instead of
+/// panicking
+pub fn parse_number<T: FromStr>(name: &str, value: &str) -> Result<T> {
+    value.parse()
+         .map_err(|_| Error::Config(format!("--{} must be a number, got `{}`", name, value)))
+}
+
+/// Like `parse_number`, additionally rejecting 0
+pub fn parse_positive(name: &str, value: &str) -> Result<usize> {
+    match try!(parse_number(name, value)) {
+        0 => Err(Error::Config(format!("--{} must be at least 1", name))),
+        n => Ok(n),
+    }
+}
+
+/// Like `parse_number`, additionally rejecting values outside of [0, 1]
+pub fn parse_fraction(name: &str, value: &str) -> Result<f32> {
+    let fraction: f32 = try!(parse_number(name, value));
+    if 0.0 <= fraction && fraction <= 1.0 {
+        Ok(fraction)
+    } else {
+        Err(Error::Config(format!("--{} must be between 0 and 1, got {}", name, value)))
+    }
+}
 
 // Stores the list of words, separated by new line
-// The first line is the length of the list, for preallocation purposes
-pub fn write_list(list: &[&str], filename: &str) {
-    let mut f = File::create(filename).unwrap();
+pub fn write_list(list: &[&str], filename: &str) -> Result<()> {
+    let mut f = try!(File::create(filename));
     for item in list {
-        writeln!(f, "{}", item).unwrap();
+        try!(writeln!(f, "{}", item));
     }
-    let _ = f.flush();
+    try!(f.flush());
+    Ok(())
 }
 
-pub fn load_list(path: &str) -> Vec<String> {
-    let mut f = File::open(path).unwrap();
+pub fn load_list(path: &str) -> Result<Vec<String>> {
+    let mut f = try!(File::open(path));
     let mut unpslit_str = String::new();
-    let _ = f.read_to_string(&mut unpslit_str).unwrap();
-    unpslit_str.lines().map(String::from).collect()
+    try!(f.read_to_string(&mut unpslit_str));
+    Ok(unpslit_str.lines().map(String::from).collect())
 }
 
-pub fn write_ndarray<T: Dimension>(nd: ArrayBase<ViewRepr<&f64>, T>, path: &str) {
-    let mut wtr = Writer::from_file(format!("./data/{}.csv", path)).unwrap();
-    // wtr.encode(nd);
+pub fn write_ndarray<T: Dimension>(nd: ArrayBase<ViewRepr<&f64>, T>, path: &str) -> Result<()> {
+    let mut wtr = try!(Writer::from_file(format!("./data/{}.csv", path)));
     for record in nd.inner_iter() {
-        let _ = wtr.encode(record);
+        try!(wtr.encode(record));
     }
+    Ok(())
+}
+
+/// Decodes every record of the CSV file at `path`. Like the files written by `write_csv`, it
+/// must not have a header row.
+pub fn read_csv<T: Decodable>(path: &str) -> Result<Vec<T>> {
+    let mut rdr = try!(Reader::from_file(path)).has_headers(false);
+    let records: result::Result<Vec<T>, _> = rdr.decode().collect();
+    Ok(try!(records))
 }
 
-pub fn write_csv_vec<T: Encodable>(v: &[Vec<T>], path: &str) {
-    let mut wtr = Writer::from_file(path).unwrap();
+/// Reads a CSV of posts, accepting files written both before and after post metadata was
+/// added to `RawPostFeatures`. Posts are written without a header row, so the first record is a
+/// post too.
+pub fn read_posts(path: &str) -> Result<Vec<RawPostFeatures>> {
+    let mut rdr = try!(Reader::from_file(path)).has_headers(false);
+    let mut posts = Vec::new();
+
+    for record in rdr.byte_records() {
+        let record = try!(record);
+        let post = if record.len() == LEGACY_COLUMNS {
+            let legacy: LegacyRawPostFeatures = try!(Decodable::decode(&mut Decoded::new(record)));
+            RawPostFeatures::from(legacy)
+        } else {
+            try!(Decodable::decode(&mut Decoded::new(record)))
+        };
+        posts.push(post);
+    }
+    Ok(posts)
+}
+
+pub fn write_csv_vec<T: Encodable>(v: &[Vec<T>], path: &str) -> Result<()> {
+    let mut wtr = try!(Writer::from_file(path));
     for record in v {
-        let _ = wtr.encode(record);
+        try!(wtr.encode(record));
     }
+    Ok(())
 }
 
-pub fn write_csv<T: Encodable>(nd: &T, path: &str) {
-    let mut wtr = Writer::from_file(path).unwrap();
-    wtr.encode(nd);
-    // for record in nd.inner_iter() {
-    //     let _ = wtr.encode(record);
-    // }
+pub fn write_csv<T: Encodable>(nd: &T, path: &str) -> Result<()> {
+    let mut wtr = try!(Writer::from_file(path));
+    try!(wtr.encode(nd));
+    Ok(())
 }
 
-pub fn deserialize_from_file<T: Decodable>(path: &str) -> T {
-    let mut f = File::open(path).unwrap();
+pub fn deserialize_from_file<T: Decodable>(path: &str) -> Result<T> {
+    let mut f = try!(File::open(path));
     let mut encoded = Vec::new();
 
-    let _ = f.read_to_end(&mut encoded).unwrap();
-    decode(&encoded[..]).unwrap()
+    try!(f.read_to_end(&mut encoded));
+    Ok(try!(decode(&encoded[..])))
 }
 
-pub fn serialize_to_file<T>(s: &T, path: &str)
+pub fn serialize_to_file<T>(s: &T, path: &str) -> Result<()>
     where T: Encodable
 {
-    let serialized: Vec<u8> = encode(&s, SizeLimit::Infinite).unwrap();
+    let serialized: Vec<u8> = try!(encode(&s, SizeLimit::Infinite));
+
+    let mut f = try!(File::create(path));
+    try!(f.write_all(&serialized[..]));
+    try!(f.flush());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::File;
+    use std::io::Write;
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(parse_number::<f32>("threshold", "0.5").unwrap(), 0.5);
+        assert!(parse_number::<usize>("folds", "abc").is_err());
+        assert_eq!(parse_positive("threads", "4").unwrap(), 4);
+        assert!(parse_positive("threads", "0").is_err());
+        assert!(parse_positive("threads", "-1").is_err());
+        assert_eq!(parse_fraction("threshold", "1").unwrap(), 1.0);
+        assert!(parse_fraction("threshold", "1.5").is_err());
+        assert!(parse_fraction("threshold", "NaN").is_err());
+    }
+
+    #[test]
+    fn test_read_legacy_posts() {
+        let dir = temp_dir("util_legacy_csv");
+        ::std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("legacy.csv");
+        let mut f = File::create(&path).unwrap();
+        write!(f, "true,a,,0,1,1,text,rust,first\nfalse,b,,0,2,2,,playrust,second\n").unwrap();
+
+        let posts = read_posts(path.to_str().unwrap()).unwrap();
+        assert_eq!(posts.iter().map(|p| p.title.as_str()).collect::<Vec<_>>(),
+                   vec!["first", "second"]);
+        assert_eq!(posts[1].subreddit, "playrust");
+    }
+}
 
-    let mut f = File::create(path).unwrap();
-    let _ = f.write_all(&serialized[..]);
-    f.flush().unwrap();
+/// A fresh, empty directory under the system temp dir for tests that touch the filesystem
+#[cfg(test)]
+pub fn temp_dir(name: &str) -> ::std::path::PathBuf {
+    let dir = ::std::env::temp_dir().join(format!("playrust_alert_{}", name));
+    let _ = ::std::fs::remove_dir_all(&dir);
+    dir
 }
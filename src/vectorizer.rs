@@ -0,0 +1,163 @@
+/// How the count of a term within a document is turned into its term frequency
+#[derive(Debug, Clone, Copy, PartialEq, RustcEncodable, RustcDecodable)]
+pub enum TfScheme {
+    /// The count itself
+    Raw,
+    /// `1 + ln(count)`, so repeating a term has diminishing returns
+    Log,
+    /// 1 if the term occurs at all
+    Binary,
+    /// `0.5 + 0.5 * count / max_count`, relative to the most frequent term of the document, so
+    /// long documents do not outweigh short ones
+    Augmented,
+}
+
+/// How every row of weights is scaled after weighting
+#[derive(Debug, Clone, Copy, PartialEq, RustcEncodable, RustcDecodable)]
+pub enum Norm {
+    None,
+    /// Weights sum to one
+    L1,
+    /// Weights form a unit vector
+    L2,
+}
+
+/// Weights term counts by TF-IDF.
+///
+/// The inverse document frequency of every column is learned from the training documents by
+/// `fit` and stored with the model, so inference weights terms by how rare they were in
+/// training rather than in the batch at hand.
+#[derive(Debug, Clone, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct TfidfVectorizer {
+    /// Smoothed inverse document frequency of every column, `ln((1 + n) / (1 + df)) + 1`
+    idf: Vec<f32>,
+    scheme: TfScheme,
+    norm: Norm,
+}
+
+impl TfidfVectorizer {
+    /// Learns the inverse document frequencies of the columns of `counts`, where `counts[i][j]`
+    /// is the number of times term `j` occurs in document `i`. Every row must have `terms`
+    /// columns.
+    pub fn fit(counts: &[Vec<f32>], terms: usize) -> TfidfVectorizer {
+        let mut document_frequency = vec![0f32; terms];
+        for row in counts {
+            for (df, &count) in document_frequency.iter_mut().zip(row) {
+                if count > 0f32 {
+                    *df += 1f32;
+                }
+            }
+        }
+
+        let documents = counts.len() as f32;
+        TfidfVectorizer {
+            idf: document_frequency.iter()
+                                   .map(|df| ((1f32 + documents) / (1f32 + df)).ln() + 1f32)
+                                   .collect(),
+            scheme: TfScheme::Raw,
+            norm: Norm::L2,
+        }
+    }
+
+    pub fn scheme(mut self, scheme: TfScheme) -> TfidfVectorizer {
+        self.scheme = scheme;
+        self
+    }
+
+    pub fn norm(mut self, norm: Norm) -> TfidfVectorizer {
+        self.norm = norm;
+        self
+    }
+
+    pub fn idf(&self) -> &[f32] {
+        &self.idf
+    }
+
+    fn term_frequency(&self, count: f32, max_count: f32) -> f32 {
+        if count <= 0f32 {
+            return 0f32;
+        }
+        match self.scheme {
+            TfScheme::Raw => count,
+            TfScheme::Log => 1f32 + count.ln(),
+            TfScheme::Binary => 1f32,
+            TfScheme::Augmented => 0.5 + 0.5 * count / max_count,
+        }
+    }
+
+    /// Weights every row of term counts. Terms absent from a document keep a weight of zero,
+    /// and so does every term of a document without any.
+    pub fn transform(&self, counts: &[Vec<f32>]) -> Vec<Vec<f32>> {
+        counts.iter()
+              .map(|row| {
+                  let max_count = row.iter().cloned().fold(0f32, f32::max);
+                  let mut weights: Vec<f32> = row.iter()
+                                                 .zip(&self.idf)
+                                                 .map(|(&count, idf)| {
+                                                     self.term_frequency(count, max_count) * idf
+                                                 })
+                                                 .collect();
+
+                  let length = match self.norm {
+                      Norm::None => 1f32,
+                      Norm::L1 => weights.iter().map(|w| w.abs()).sum::<f32>(),
+                      Norm::L2 => weights.iter().map(|w| w * w).sum::<f32>().sqrt(),
+                  };
+                  if length > 0f32 {
+                      for weight in &mut weights {
+                          *weight /= length;
+                      }
+                  }
+                  weights
+              })
+              .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts() -> Vec<Vec<f32>> {
+        vec![vec![2f32, 1f32, 0f32], vec![0f32, 1f32, 0f32], vec![0f32, 0f32, 0f32]]
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_idf() {
+        let vectorizer = TfidfVectorizer::fit(&counts(), 3);
+        assert_close(vectorizer.idf(),
+                     &[(4f32 / 2f32).ln() + 1f32, (4f32 / 3f32).ln() + 1f32, 4f32.ln() + 1f32]);
+    }
+
+    #[test]
+    fn test_schemes() {
+        let vectorizer = TfidfVectorizer::fit(&counts(), 3).norm(Norm::None);
+        let idf = vectorizer.idf().to_vec();
+        let first_row = |scheme| vectorizer.clone().scheme(scheme).transform(&counts())[0].clone();
+
+        assert_close(&first_row(TfScheme::Raw), &[2f32 * idf[0], idf[1], 0f32]);
+        assert_close(&first_row(TfScheme::Log), &[(1f32 + 2f32.ln()) * idf[0], idf[1], 0f32]);
+        assert_close(&first_row(TfScheme::Binary), &[idf[0], idf[1], 0f32]);
+        assert_close(&first_row(TfScheme::Augmented), &[idf[0], 0.75 * idf[1], 0f32]);
+    }
+
+    #[test]
+    fn test_norms() {
+        let vectorizer = TfidfVectorizer::fit(&counts(), 3).scheme(TfScheme::Binary);
+
+        let l2 = vectorizer.transform(&counts());
+        assert_close(&l2[1], &[0f32, 1f32, 0f32]);
+        assert!((l2[0].iter().map(|w| w * w).sum::<f32>() - 1f32).abs() < 1e-5);
+        assert_close(&l2[2], &[0f32, 0f32, 0f32]);
+
+        let l1 = vectorizer.norm(Norm::L1).transform(&counts());
+        assert!((l1[0].iter().sum::<f32>() - 1f32).abs() < 1e-5);
+    }
+}
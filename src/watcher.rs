@@ -0,0 +1,287 @@
+use alert::Alert;
+use error::Result;
+use model::{ModelBundle, Outcome, Prediction};
+use reddit::{RawPostFeatures, RedditClient, parse_post};
+use transport::Transport;
+
+use std::collections::{HashSet, VecDeque};
+use std::io::{self, Write};
+
+/// A set of post ids that forgets the oldest ones once it holds `capacity` of them, so a
+/// watcher running for months does not grow without bound
+#[derive(Debug, Clone)]
+pub struct SeenIds {
+    capacity: usize,
+    order: VecDeque<String>,
+    ids: HashSet<String>,
+}
+
+impl SeenIds {
+    pub fn new(capacity: usize) -> SeenIds {
+        SeenIds {
+            capacity: capacity,
+            order: VecDeque::new(),
+            ids: HashSet::new(),
+        }
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids.contains(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Remembers `id`, returning false if it was already seen
+    pub fn insert(&mut self, id: &str) -> bool {
+        if self.ids.contains(id) {
+            return false;
+        }
+
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        self.order.push_back(id.to_owned());
+        self.ids.insert(id.to_owned());
+        true
+    }
+}
+
+/// The posts classified during one poll
+#[derive(Debug, Clone)]
+pub struct Poll {
+    /// Every post that had not been seen before, with its prediction
+    pub classified: Vec<(RawPostFeatures, Prediction)>,
+    /// The new posts predicted as the alert label
+    pub alerts: Vec<Alert>,
+}
+
+/// Watches the newest posts of a subreddit for posts the model assigns to `alert_label`
+pub struct Watcher {
+    subreddit: String,
+    alert_label: String,
+    page_size: u32,
+    skip_backlog: bool,
+    explained_features: usize,
+    seen: SeenIds,
+    /// Whether a page has been fetched yet. The backlog is the first page fetched, even if it
+    /// was empty.
+    polled: bool,
+}
+
+impl Watcher {
+    pub fn new(subreddit: &str, alert_label: &str) -> Watcher {
+        Watcher {
+            subreddit: subreddit.to_owned(),
+            alert_label: alert_label.to_owned(),
+            page_size: 25,
+            skip_backlog: false,
+            explained_features: 5,
+            seen: SeenIds::new(10000),
+            polled: false,
+        }
+    }
+
+    /// Number of the newest posts requested on every poll
+    pub fn page_size(mut self, page_size: u32) -> Watcher {
+        self.page_size = page_size;
+        self
+    }
+
+    /// Only remember the posts of the first poll instead of classifying them, so restarting the
+    /// watcher does not alert on posts that may have been handled already
+    pub fn skip_backlog(mut self, skip_backlog: bool) -> Watcher {
+        self.skip_backlog = skip_backlog;
+        self
+    }
+
+    /// Number of features attached to every alert, both of the features that contributed most
+    /// to its prediction and of those most unusual compared to the training posts
+    pub fn explained_features(mut self, explained_features: usize) -> Watcher {
+        self.explained_features = explained_features;
+        self
+    }
+
+    /// Number of post ids remembered
+    pub fn seen_capacity(mut self, capacity: usize) -> Watcher {
+        self.seen = SeenIds::new(capacity);
+        self
+    }
+
+    fn remember(&mut self, posts: &[RawPostFeatures]) {
+        for post in posts {
+            self.seen.insert(post.dedup_key());
+        }
+    }
+
+    /// Fetches the newest posts and classifies those not seen before
+    pub fn poll_once<T: Transport>(&mut self,
+                                   client: &mut RedditClient<T>,
+                                   bundle: &ModelBundle)
+                                   -> Result<Poll> {
+        let (page, _) = try!(client.get_raw_features(&self.subreddit, self.page_size, &None));
+
+        // Posts are only remembered once classified, so a failed poll retries them
+        let mut posts: Vec<RawPostFeatures> = Vec::new();
+        for value in &page {
+            match parse_post(value) {
+                Ok(post) => {
+                    let new = !self.seen.contains(post.dedup_key()) &&
+                              !posts.iter().any(|p| p.dedup_key() == post.dedup_key());
+                    if new {
+                        posts.push(post);
+                    }
+                }
+                Err(e) => {
+                    let _ = writeln!(io::stderr(), "skipping malformed post: {}", e);
+                }
+            }
+        }
+
+        if !self.polled && self.skip_backlog {
+            self.remember(&posts);
+            posts.clear();
+        }
+        self.polled = true;
+
+        // Oldest first, the order they were posted in
+        posts.reverse();
+        let predictions = try!(bundle.classify(&posts));
+
+        let alerted: Vec<usize> = (0..posts.len())
+                                      .filter(|&i| {
+                                          predictions[i].outcome ==
+                                          Outcome::Label(self.alert_label.clone())
+                                      })
+                                      .collect();
+        let alerted_posts: Vec<RawPostFeatures> = alerted.iter()
+                                                         .map(|&i| posts[i].clone())
+                                                         .collect();
+        let contributions = try!(bundle.feature_contributions(&alerted_posts,
+                                                              self.explained_features));
+        let deviations = bundle.unusual_features(&alerted_posts, self.explained_features);
+
+        let alerts = alerted.iter()
+                            .zip(contributions.into_iter().zip(deviations))
+                            .map(|(&i, (contributions, deviations))| {
+                                Alert::new(&posts[i], &predictions[i])
+                                    .with_contributions(contributions)
+                                    .with_unusual_features(deviations)
+                            })
+                            .collect();
+        self.remember(&posts);
+
+        Ok(Poll {
+            classified: posts.into_iter().zip(predictions).collect(),
+            alerts: alerts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::test_bundle;
+    use ratelimit::RateLimit;
+    use reddit::RedditClient;
+    use transport::{FixtureTransport, Method, Response};
+
+    use rustlearn::prelude::*;
+    use rustlearn::ensemble::random_forest::Hyperparameters;
+    use rustlearn::trees::decision_tree;
+
+    use std::fs;
+    use std::io::Read;
+
+    fn fixture_client() -> RedditClient<FixtureTransport> {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/reddit");
+        RedditClient::with_transport(FixtureTransport::from_dir(dir))
+            .rate_limit(RateLimit::unlimited())
+    }
+
+    #[test]
+    fn test_seen_ids_are_bounded() {
+        let mut seen = SeenIds::new(2);
+        assert!(seen.insert("a"));
+        assert!(!seen.insert("a"));
+        seen.insert("b");
+        seen.insert("c");
+
+        assert_eq!(seen.len(), 2);
+        assert!(!seen.contains("a"));
+        assert!(seen.contains("c"));
+    }
+
+    #[test]
+    fn test_poll_classifies_new_posts_once() {
+        let bundle = test_bundle();
+        let mut client = fixture_client();
+        let mut watcher = Watcher::new("rust", "playrust").page_size(2);
+
+        let poll = watcher.poll_once(&mut client, &bundle).unwrap();
+        let ids: Vec<&str> = poll.classified.iter().map(|c| c.0.id.as_str()).collect();
+        assert_eq!(ids, vec!["4u0002", "4u0003"]);
+        assert!(poll.alerts.iter().all(|a| a.label == "playrust"));
+
+        let poll = watcher.poll_once(&mut client, &bundle).unwrap();
+        assert!(poll.classified.is_empty());
+    }
+
+    #[test]
+    fn test_failed_classification_is_retried() {
+        // A forest fit on a different number of features can not classify anything
+        let mut broken = test_bundle();
+        let x = Array::from(&vec![vec![0f32], vec![1f32]]);
+        let y = Array::from(vec![0f32, 1f32]);
+        let mut forest = Hyperparameters::new(decision_tree::Hyperparameters::new(1), 2)
+                             .one_vs_rest();
+        forest.fit(&x, &y).unwrap();
+        broken.forest = forest;
+
+        let mut client = fixture_client();
+        let mut watcher = Watcher::new("rust", "playrust").page_size(2);
+        assert!(watcher.poll_once(&mut client, &broken).is_err());
+
+        let poll = watcher.poll_once(&mut client, &test_bundle()).unwrap();
+        assert_eq!(poll.classified.len(), 2);
+    }
+
+    #[test]
+    fn test_skip_backlog() {
+        let bundle = test_bundle();
+        let mut watcher = Watcher::new("rust", "playrust").page_size(2).skip_backlog(true);
+
+        let poll = watcher.poll_once(&mut fixture_client(), &bundle).unwrap();
+        assert!(poll.classified.is_empty());
+    }
+
+    #[test]
+    fn test_skip_backlog_only_skips_first_page() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/reddit");
+        let page = fs::File::open(format!("{}/get_r_rust_new_json_sort_new_limit_2.json", dir));
+        let mut body = String::new();
+        page.unwrap().read_to_string(&mut body).unwrap();
+
+        // A subreddit without any posts yet, then two new posts
+        let path = "/r/rust/new.json?sort=new&limit=2";
+        let mut transport = FixtureTransport::new();
+        transport.insert(Method::Get,
+                         path,
+                         Response::ok(r#"{"data": {"after": null, "children": []}}"#.to_owned()));
+        transport.insert(Method::Get, path, Response::ok(body));
+        let mut client = RedditClient::with_transport(transport)
+                             .rate_limit(RateLimit::unlimited());
+
+        let bundle = test_bundle();
+        let mut watcher = Watcher::new("rust", "playrust").page_size(2).skip_backlog(true);
+        assert!(watcher.poll_once(&mut client, &bundle).unwrap().classified.is_empty());
+        assert_eq!(watcher.poll_once(&mut client, &bundle).unwrap().classified.len(), 2);
+    }
+}